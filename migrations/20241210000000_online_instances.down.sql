@@ -0,0 +1,2 @@
+
+DROP TABLE online_instances;
@@ -0,0 +1,7 @@
+
+CREATE UNLOGGED TABLE online_instances (
+	id UUID PRIMARY KEY,
+	users INTEGER NOT NULL DEFAULT 0,
+	viewers INTEGER NOT NULL DEFAULT 0,
+	updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+)
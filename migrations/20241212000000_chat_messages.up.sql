@@ -0,0 +1,7 @@
+
+CREATE TABLE chat_messages (
+	id SERIAL PRIMARY KEY,
+	user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+	body VARCHAR(256) NOT NULL,
+	created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+)
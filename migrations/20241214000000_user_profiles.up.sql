@@ -0,0 +1,4 @@
+
+ALTER TABLE users
+	ADD COLUMN created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+	ADD COLUMN pixels_placed INTEGER NOT NULL DEFAULT 0
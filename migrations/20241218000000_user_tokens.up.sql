@@ -0,0 +1,11 @@
+
+CREATE TABLE user_tokens (
+	token_hash TEXT PRIMARY KEY,
+	user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+	purpose TEXT NOT NULL,
+	created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+	expires_at TIMESTAMPTZ NOT NULL,
+	consumed_at TIMESTAMPTZ
+);
+
+CREATE INDEX user_tokens_user_id_purpose ON user_tokens (user_id, purpose);
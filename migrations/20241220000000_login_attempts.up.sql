@@ -0,0 +1,11 @@
+
+CREATE TABLE login_attempts (
+	id BIGSERIAL PRIMARY KEY,
+	email TEXT NOT NULL,
+	ip TEXT NOT NULL,
+	succeeded BOOLEAN NOT NULL,
+	created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX login_attempts_email ON login_attempts (email, created_at);
+CREATE INDEX login_attempts_ip ON login_attempts (ip, created_at);
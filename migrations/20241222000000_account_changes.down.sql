@@ -0,0 +1,4 @@
+
+DROP TABLE username_changes;
+
+ALTER TABLE user_tokens DROP COLUMN payload;
@@ -0,0 +1,12 @@
+
+ALTER TABLE user_tokens ADD COLUMN payload TEXT;
+
+CREATE TABLE username_changes (
+	id SERIAL PRIMARY KEY,
+	user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+	old_username VARCHAR(25) NOT NULL,
+	new_username VARCHAR(25) NOT NULL,
+	created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX username_changes_user_id ON username_changes (user_id, created_at);
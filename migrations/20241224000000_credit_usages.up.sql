@@ -0,0 +1,9 @@
+
+CREATE TABLE credit_usages (
+	id BIGSERIAL PRIMARY KEY,
+	user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+	free BOOLEAN NOT NULL,
+	created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX credit_usages_user_id ON credit_usages (user_id);
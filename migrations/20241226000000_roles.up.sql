@@ -0,0 +1,40 @@
+
+CREATE TABLE roles (
+	id SERIAL PRIMARY KEY,
+	name VARCHAR(25) NOT NULL UNIQUE
+);
+
+CREATE TABLE role_permissions (
+	role_id INTEGER NOT NULL REFERENCES roles(id) ON DELETE CASCADE,
+	permission TEXT NOT NULL,
+	PRIMARY KEY (role_id, permission)
+);
+
+CREATE TABLE user_roles (
+	user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+	role_id INTEGER NOT NULL REFERENCES roles(id) ON DELETE CASCADE,
+	PRIMARY KEY (user_id, role_id)
+);
+
+INSERT INTO roles (name) VALUES ('user'), ('moderator'), ('admin');
+
+INSERT INTO role_permissions (role_id, permission)
+SELECT r.id, p.permission
+FROM roles r
+JOIN (VALUES
+	('user', 'paint'),
+	('user', 'chat'),
+	('moderator', 'paint'),
+	('moderator', 'chat'),
+	('moderator', 'moderate_chat'),
+	('admin', 'paint'),
+	('admin', 'chat'),
+	('admin', 'moderate_chat'),
+	('admin', 'manage_users'),
+	('admin', 'manage_roles')
+) AS p (role, permission) ON p.role = r.name;
+
+INSERT INTO user_roles (user_id, role_id)
+SELECT u.id, r.id
+FROM users u, roles r
+WHERE r.name = 'user';
@@ -0,0 +1,4 @@
+
+DROP TABLE oauth_identities;
+
+ALTER TABLE users ALTER COLUMN password SET NOT NULL;
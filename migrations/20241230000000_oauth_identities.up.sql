@@ -0,0 +1,10 @@
+CREATE TABLE oauth_identities (
+	provider TEXT NOT NULL,
+	subject TEXT NOT NULL,
+	user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+	created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+	PRIMARY KEY (provider, subject),
+	UNIQUE (user_id, provider)
+);
+
+ALTER TABLE users ALTER COLUMN password DROP NOT NULL;
@@ -0,0 +1,6 @@
+
+DROP TABLE recovery_codes;
+
+DROP TABLE two_factor;
+
+ALTER TABLE roles DROP COLUMN requires_two_factor;
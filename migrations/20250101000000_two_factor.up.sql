@@ -0,0 +1,17 @@
+CREATE TABLE two_factor (
+	user_id INTEGER PRIMARY KEY REFERENCES users(id) ON DELETE CASCADE,
+	secret TEXT NOT NULL,
+	last_used_step BIGINT,
+	created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+	enabled_at TIMESTAMPTZ
+);
+
+CREATE TABLE recovery_codes (
+	code_hash TEXT PRIMARY KEY,
+	user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+	used_at TIMESTAMPTZ
+);
+
+CREATE INDEX recovery_codes_user_id ON recovery_codes (user_id);
+
+ALTER TABLE roles ADD COLUMN requires_two_factor BOOLEAN NOT NULL DEFAULT false;
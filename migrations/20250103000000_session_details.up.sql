@@ -0,0 +1,4 @@
+ALTER TABLE sessions
+	ADD COLUMN ip TEXT,
+	ADD COLUMN user_agent TEXT,
+	ADD COLUMN last_seen_at TIMESTAMPTZ NOT NULL DEFAULT NOW();
@@ -0,0 +1,4 @@
+DROP TABLE username_denylist;
+
+ALTER TABLE users
+	DROP COLUMN canonical_username;
@@ -0,0 +1,17 @@
+-- the skeletons can't be computed here, the existing users get a placeholder
+-- the server replaces when it starts, see User::canonicalize_usernames.
+ALTER TABLE users
+	ADD COLUMN canonical_username TEXT;
+
+UPDATE users
+SET canonical_username = '#' || id;
+
+ALTER TABLE users
+	ALTER COLUMN canonical_username SET NOT NULL,
+	ADD CONSTRAINT users_canonical_username_key UNIQUE (canonical_username);
+
+CREATE TABLE username_denylist (
+	canonical TEXT PRIMARY KEY,
+	term TEXT NOT NULL,
+	created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
@@ -0,0 +1,2 @@
+ALTER TABLE sessions
+	ADD COLUMN refreshed_at TIMESTAMPTZ NOT NULL DEFAULT NOW();
@@ -0,0 +1 @@
+DROP TABLE painted_pixels;
@@ -0,0 +1,9 @@
+-- accounts whose emails only differ in casing make this fail,
+-- they have to be merged by hand first.
+UPDATE users
+SET email = LOWER(email)
+WHERE email <> LOWER(email);
+
+ALTER TABLE users DROP CONSTRAINT users_email_key;
+
+CREATE UNIQUE INDEX users_email_lower_key ON users (LOWER(email));
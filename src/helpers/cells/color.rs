@@ -1,4 +1,5 @@
-use std::num::ParseIntError;
+use std::{fmt::{Display, Formatter, Result as FmtResult}, num::ParseIntError};
+use serde::Serialize;
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -13,7 +14,7 @@ pub enum ColorError {
     InvalidFormat
 }
 
-#[derive(Clone, Copy)]
+#[derive(Serialize, Clone, Copy)]
 pub struct Color {
     r: u8,
     g: u8,
@@ -42,11 +43,11 @@ impl Color {
     }
 }
 
-impl Into<i32> for Color {
-    fn into(self) -> i32 {
-        ((self.r as i32) << 16)
-            | ((self.g as i32) << 8)
-            | (self.b as i32)
+impl From<Color> for i32 {
+    fn from(value: Color) -> Self {
+        ((value.r as i32) << 16)
+            | ((value.g as i32) << 8)
+            | (value.b as i32)
     }
 }
 
@@ -100,8 +101,8 @@ impl TryFrom<String> for Color {
     }
 }
 
-impl ToString for Color {
-    fn to_string(&self) -> String {
-        format!("v,{},{},{}", self.r, self.g, self.b)
+impl Display for Color {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(f, "v,{},{},{}", self.r, self.g, self.b)
     }
 }
@@ -0,0 +1,104 @@
+use std::{collections::VecDeque, sync::{Arc, Mutex}};
+use lazy_static::lazy_static;
+use serde::Serialize;
+use tokio::sync::broadcast::{channel, Receiver, Sender};
+use crate::models::user::User;
+use super::{color::Color, position::Position};
+
+const BACKLOG_SIZE: usize = 1024;
+
+#[derive(Serialize)]
+pub struct CellEvent {
+    seq: u64,
+    author: String,
+    position: Position,
+    color: Color
+}
+
+impl CellEvent {
+    pub fn seq(&self) -> u64 {
+        self.seq
+    }
+
+    pub fn author(&self) -> &String {
+        &self.author
+    }
+
+    pub fn position(&self) -> Position {
+        self.position
+    }
+
+    pub fn color(&self) -> Color {
+        self.color
+    }
+}
+
+struct EventLog {
+    next_seq: u64,
+    backlog: VecDeque<Arc<CellEvent>>,
+    sender: Sender<Arc<CellEvent>>
+}
+
+lazy_static! {
+    static ref EVENTS: Mutex<EventLog> = Mutex::new(EventLog {
+        next_seq: 1,
+        backlog: VecDeque::with_capacity(BACKLOG_SIZE),
+        sender: channel(BACKLOG_SIZE).0
+    });
+}
+
+// every written cell goes through here, both the socket sessions
+// and the event streams are fed from the same sequence.
+pub fn publish(author: &User, position: Position, color: Color) {
+    let mut events = EVENTS
+        .lock()
+        .unwrap_or_else(|err| err.into_inner());
+
+    let event = Arc::new(CellEvent {
+        seq: events.next_seq,
+        author: author.name().clone(),
+        position,
+        color
+    });
+
+    events.next_seq += 1;
+
+    if events.backlog.len() == BACKLOG_SIZE {
+        events.backlog.pop_front();
+    }
+
+    events.backlog.push_back(event.clone());
+
+    // there may be no receivers, that's fine.
+    let _ = events.sender.send(event);
+}
+
+// subscribes to the events published from now on, if `after` is specified
+// the events after that sequence are returned to resume from it, None is returned
+// in place of them if they are no longer in the backlog.
+pub fn subscribe(after: Option<u64>) -> (Option<Vec<Arc<CellEvent>>>, Receiver<Arc<CellEvent>>) {
+    let events = EVENTS
+        .lock()
+        .unwrap_or_else(|err| err.into_inner());
+
+    let missed = match after {
+        None => Some(Vec::new()),
+
+        Some(after) if after >= events.next_seq => None,
+
+        Some(after) => {
+            let oldest = events.backlog
+                .front()
+                .map_or(events.next_seq, |event| event.seq);
+
+            (after + 1 >= oldest).then(|| events.backlog
+                .iter()
+                .filter(|event| event.seq > after)
+                .cloned()
+                .collect()
+            )
+        }
+    };
+
+    (missed, events.sender.subscribe())
+}
@@ -2,3 +2,4 @@
 pub mod color;
 pub mod position;
 pub mod processes;
+pub mod events;
@@ -1,4 +1,5 @@
-use std::num::ParseIntError;
+use std::{fmt::{Display, Formatter, Result as FmtResult}, num::ParseIntError};
+use serde::Serialize;
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -10,7 +11,7 @@ pub enum PositionError {
     ParseI32(#[from] ParseIntError)
 }
 
-#[derive(Clone, Copy)]
+#[derive(Serialize, Clone, Copy)]
 pub struct Position {
     x: u32,
     y: u32
@@ -58,8 +59,8 @@ impl PartialEq for Position {
     }
 }
 
-impl ToString for Position {
-    fn to_string(&self) -> String {
-        format!("{},{}", self.x, self.y)
+impl Display for Position {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(f, "{},{}", self.x, self.y)
     }
 }
@@ -1,19 +1,27 @@
-use std::{fs::{File, OpenOptions}, io::{Result as IoResult, Read, Seek, SeekFrom, Write}, sync::OnceLock};
+use std::{fmt::{Display, Formatter, Result as FmtResult}, fs::{File, OpenOptions}, io::{Result as IoResult, Read, Seek, SeekFrom, Write}, sync::{Mutex, MutexGuard, OnceLock}};
+use actix_web::web::block;
 use crate::models::user::User;
 use super::{color::Color, position::Position};
 
 const WIDTH: u32 = 1920;
 const HEIGHT: u32 = 1080;
 
+// rgb followed by the little endian id of the author.
+const CELL_SIZE: usize = 7;
+
+// no user has this id, anonymized cells are attributed to it.
+const ANONYMOUS_AUTHOR: i32 = 0;
+
 pub struct CanvasSpec {
     columns: u32,
     rows: u32,
     pub cells: Vec<u8>
 }
 
-impl ToString for CanvasSpec {
-    fn to_string(&self) -> String {
-        format!(
+impl Display for CanvasSpec {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(
+            f,
             "{},{},{}",
             self.columns,
             self.rows,
@@ -26,26 +34,33 @@ impl ToString for CanvasSpec {
     }
 }
 
-static CELLS_FILE: OnceLock<File> = OnceLock::new();
+// the file has a single cursor, every access seeks it so they are serialized.
+static CELLS_FILE: OnceLock<Mutex<File>> = OnceLock::new();
 
-fn open_file() -> IoResult<&'static File> {
+fn open_file() -> IoResult<MutexGuard<'static, File>> {
     if let Some(file) = CELLS_FILE.get() {
-        return Ok(file)
+        return Ok(file.lock().unwrap_or_else(|err| err.into_inner()))
     }
 
-    let mut file = OpenOptions::new()
+    let file = OpenOptions::new()
         .read(true)
         .write(true)
         .create(true)
+        .truncate(false)
         .open("cells.bin")?;
 
-    const SIZE: usize = (HEIGHT * WIDTH * 7) as usize;
+    const SIZE: u64 = (HEIGHT * WIDTH) as u64 * CELL_SIZE as u64;
 
-    if !file.read(&mut [])? != SIZE {
-        file.write(&[0u8; SIZE])?;
+    // new files are filled with blank cells, existing ones keep theirs.
+    if file.metadata()?.len() != SIZE {
+        file.set_len(SIZE)?;
     }
 
-    Ok(CELLS_FILE.get_or_init(|| file))
+    Ok(
+        CELLS_FILE.get_or_init(|| Mutex::new(file))
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+    )
 }
 
 // this will run every time someone paints in a cell.
@@ -65,7 +80,7 @@ pub fn process_written_cell(author: &User, position: Position, color: Color) ->
     let mut file = open_file()
         .map_err(|err| err.to_string())?;
 
-    file.seek(SeekFrom::Start(((position.y() * WIDTH + position.x()) as u64) * 7))
+    file.seek(SeekFrom::Start((position.y() * WIDTH + position.x()) as u64 * CELL_SIZE as u64))
         .map_err(|err| err.to_string())?;
 
     file.write_all(&buffer)
@@ -77,16 +92,78 @@ pub fn process_written_cell(author: &User, position: Position, color: Color) ->
 // this will run every time someone opens a connection for the first time.
 // if you return Ok(_) it will send the canvas spec, otherwise simply close the connection.
 pub fn get_canvas_spec() -> Result<CanvasSpec, String> {
-    let mut file = open_file()
-        .map_err(|err| err.to_string())?;
-
-    let mut buffer = Vec::new();
-    file.read_to_end(&mut buffer)
+    let cells = read_cells()
         .map_err(|err| err.to_string())?;
 
     Ok(CanvasSpec {
         columns: WIDTH,
         rows: HEIGHT,
-        cells: buffer
+        cells
+    })
+}
+
+fn read_cells() -> IoResult<Vec<u8>> {
+    read_from(&mut *open_file()?)
+}
+
+fn read_from(file: &mut File) -> IoResult<Vec<u8>> {
+    let mut buffer = vec![0u8; (WIDTH * HEIGHT) as usize * CELL_SIZE];
+
+    file.seek(SeekFrom::Start(0))?;
+    file.read_exact(&mut buffer)?;
+
+    Ok(buffer)
+}
+
+fn authored_cells(cells: &[u8], author_id: i32) -> impl Iterator<Item = (usize, &[u8])> {
+    let author = author_id.to_le_bytes();
+
+    cells.chunks_exact(CELL_SIZE)
+        .enumerate()
+        .filter(move |(_, cell)| cell[3..] == author)
+}
+
+// the cells where the color painted by the author is still visible. going through
+// the whole file takes a while, so it runs in the blocking pool instead of a worker,
+// and the file is only locked while it's copied.
+pub async fn cells_by_author(author_id: i32) -> Result<Vec<(Position, Color)>, String> {
+    block(move || {
+        let cells = read_cells()
+            .map_err(|err| err.to_string())?;
+
+        Ok(
+            authored_cells(&cells, author_id)
+                .map(|(index, cell)| (
+                    Position::new(index as u32 % WIDTH, index as u32 / WIDTH),
+                    Color::new(cell[0], cell[1], cell[2])
+                ))
+                .collect()
+        )
+    })
+        .await
+        .map_err(|err| err.to_string())?
+}
+
+// the colors stay, only the author id is overwritten. the file stays locked
+// in between, so cells painted meanwhile keep their author.
+pub async fn anonymize_author(author_id: i32) -> Result<(), String> {
+    block(move || {
+        let mut file = open_file()
+            .map_err(|err| err.to_string())?;
+
+        let cells = read_from(&mut file)
+            .map_err(|err| err.to_string())?;
+
+        for (index, _) in authored_cells(&cells, author_id) {
+            file.seek(SeekFrom::Start((index * CELL_SIZE + 3) as u64))
+                .map_err(|err| err.to_string())?;
+
+            file.write_all(&ANONYMOUS_AUTHOR.to_le_bytes())
+                .map_err(|err| err.to_string())?;
+        }
+
+        Ok(())
     })
+        .await
+        .map_err(|err| err.to_string())?
 }
@@ -0,0 +1,49 @@
+use std::{collections::HashSet, fs::read_to_string, sync::OnceLock};
+
+static BLOCKED_WORDS: OnceLock<HashSet<String>> = OnceLock::new();
+
+// the blocked words are read once from blocked_words.txt, one word per line,
+// if the file doesn't exist nothing is filtered.
+fn blocked_words() -> &'static HashSet<String> {
+    BLOCKED_WORDS.get_or_init(|| {
+        read_to_string("blocked_words.txt")
+            .unwrap_or_default()
+            .lines()
+            .map(|word| word.trim().to_lowercase())
+            .filter(|word| !word.is_empty())
+            .collect()
+    })
+}
+
+// replaces every blocked word in the message with asterisks,
+// words are compared ignoring case.
+pub fn censor(message: &str) -> String {
+    let blocked = blocked_words();
+
+    if blocked.is_empty() {
+        return message.to_string();
+    }
+
+    let mut censored = String::with_capacity(message.len());
+    let mut word = String::new();
+
+    for c in message.chars().chain([' ']) {
+        if c.is_alphanumeric() {
+            word.push(c);
+            continue;
+        }
+
+        if blocked.contains(&word.to_lowercase()) {
+            censored.extend(word.chars().map(|_| '*'));
+        } else {
+            censored.push_str(&word);
+        }
+
+        word.clear();
+        censored.push(c);
+    }
+
+    censored.pop();
+
+    censored
+}
@@ -0,0 +1,42 @@
+use std::{collections::HashMap, hash::Hash, sync::Mutex, time::{Duration, Instant}};
+
+// in memory cache where every entry lives for `ttl`,
+// it's meant to save queries for rows read on every request.
+pub struct Cache<K, V> {
+    ttl: Duration,
+    entries: Mutex<HashMap<K, (Instant, V)>>
+}
+
+impl<K: Eq + Hash, V: Clone> Cache<K, V> {
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            entries: Mutex::new(HashMap::new())
+        }
+    }
+
+    pub fn get(&self, key: &K) -> Option<V> {
+        self.entries
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+            .get(key)
+            .filter(|(inserted, _)| inserted.elapsed() < self.ttl)
+            .map(|(_, value)| value.clone())
+    }
+
+    pub fn insert(&self, key: K, value: V) {
+        let mut entries = self.entries
+            .lock()
+            .unwrap_or_else(|err| err.into_inner());
+
+        entries.retain(|_, (inserted, _)| inserted.elapsed() < self.ttl);
+        entries.insert(key, (Instant::now(), value));
+    }
+
+    pub fn invalidate(&self, key: &K) {
+        self.entries
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+            .remove(key);
+    }
+}
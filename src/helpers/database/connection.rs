@@ -24,7 +24,7 @@ pub async fn get_db_connection<'r>() -> Result<&'r Pool<Postgres>, DbConnectionE
 
     let pool = PgPoolOptions::new()
         .max_connections(5)
-        .connect(&env!("DATABASE_URL"))
+        .connect(env!("DATABASE_URL"))
         .await?;
 
     Migrator::new(Path::new("./migrations"))
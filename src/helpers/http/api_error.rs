@@ -0,0 +1,260 @@
+use std::{fmt::Display, time::Duration};
+use actix_web::{http::StatusCode, Error as ActixError, HttpRequest, HttpResponse, ResponseError};
+use jsonwebtoken::errors::Error as JwtError;
+use log::{error, warn};
+use sqlx::Error as SqlxError;
+use thiserror::Error;
+use time::OffsetDateTime;
+use url::ParseError;
+use crate::{helpers::{cells::{color::ColorError, position::PositionError}, database::connection::DbConnectionError, oauth::provider::OAuthError}, models::{account_export::ExportError, api_token::ApiTokenError, chat_message::ChatError, login_attempt::LoginAttemptError, oauth_identity::IdentityError, online::OnlineError, role::RoleError, two_factor::TwoFactorError, user::UserError, user_session::SessionError, user_token::TokenError, username_denylist::DenylistError}};
+use super::envelope::{Envelope, FieldErrors};
+
+// the error of every endpoint, sent in an envelope. what the client did wrong is
+// sent as is, what went wrong in the server is logged and replaced with a generic message.
+//
+// pub async fn handler() -> Result<HttpResponse, ApiError>
+#[derive(Error, Debug)]
+pub enum ApiError {
+    #[error("{0}")]
+    BadRequest(String),
+
+    #[error("Some fields are not valid.")]
+    InvalidFields(FieldErrors),
+
+    #[error("{0}")]
+    Unauthorized(String),
+
+    #[error("{0}")]
+    Forbidden(String),
+
+    #[error("{0}")]
+    NotFound(String),
+
+    #[error("{0}")]
+    Conflict(String),
+
+    // a rule of the account that stops applying at some point, unlike rate limits.
+    #[error("{0}")]
+    NotYet(String, OffsetDateTime),
+
+    #[error("{0}")]
+    TooManyRequests(String, Duration),
+
+    // the detail is only logged.
+    #[error("The provider couldn't complete the login, try again.")]
+    Provider(String),
+
+    // the detail is only logged.
+    #[error("Something went wrong, try again later.")]
+    Internal(String)
+}
+
+impl ApiError {
+    pub fn bad_request(message: impl ToString) -> Self {
+        Self::BadRequest(message.to_string())
+    }
+
+    pub fn unauthorized(message: impl ToString) -> Self {
+        Self::Unauthorized(message.to_string())
+    }
+
+    pub fn forbidden(message: impl ToString) -> Self {
+        Self::Forbidden(message.to_string())
+    }
+
+    pub fn not_found(message: impl ToString) -> Self {
+        Self::NotFound(message.to_string())
+    }
+
+    pub fn conflict(message: impl ToString) -> Self {
+        Self::Conflict(message.to_string())
+    }
+
+    pub fn not_yet(message: impl ToString, available_at: OffsetDateTime) -> Self {
+        Self::NotYet(message.to_string(), available_at)
+    }
+
+    pub fn too_many_requests(message: impl ToString, retry: Duration) -> Self {
+        Self::TooManyRequests(message.to_string(), retry)
+    }
+
+    pub fn internal(detail: impl ToString) -> Self {
+        Self::Internal(detail.to_string())
+    }
+}
+
+impl ResponseError for ApiError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            Self::BadRequest(_) | Self::InvalidFields(_) => StatusCode::BAD_REQUEST,
+            Self::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            Self::Forbidden(_) => StatusCode::FORBIDDEN,
+            Self::NotFound(_) => StatusCode::NOT_FOUND,
+            Self::Conflict(_) | Self::NotYet(..) => StatusCode::CONFLICT,
+            Self::TooManyRequests(..) => StatusCode::TOO_MANY_REQUESTS,
+            Self::Provider(_) => StatusCode::BAD_GATEWAY,
+            Self::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let mut res = HttpResponse::build(self.status_code());
+
+        match self {
+            Self::Internal(detail) => error!("{detail}"),
+            Self::Provider(detail) => warn!("{detail}"),
+            Self::TooManyRequests(_, retry) => {
+                res.insert_header(("Retry-After", (retry.as_secs() + 1).to_string()));
+            },
+            _ => {}
+        }
+
+        match self {
+            Self::InvalidFields(fields) => res.json(Envelope::fields(self, fields.clone())),
+            Self::NotYet(_, available_at) => res.json(Envelope::available_at(self, *available_at)),
+            _ => res.json(Envelope::error(self))
+        }
+    }
+}
+
+// the error handler of the extractors of actix, so a malformed
+// path or query is answered in an envelope like everything else.
+//
+// App::new().app_data(QueryConfig::default().error_handler(invalid_request))
+pub fn invalid_request(err: impl Display, _req: &HttpRequest) -> ActixError {
+    ApiError::bad_request(format!("The request is not valid: {err}"))
+        .into()
+}
+
+// errors that can only come from the server.
+macro_rules! internal_errors {
+    ($($error:ty),*) => {
+        $(
+            impl From<$error> for ApiError {
+                fn from(err: $error) -> Self {
+                    Self::Internal(format!("{err:#}"))
+                }
+            }
+        )*
+    };
+}
+
+internal_errors!(SqlxError, DbConnectionError, JwtError, ParseError, TokenError, OnlineError, ChatError, ExportError);
+
+impl From<UserError> for ApiError {
+    fn from(err: UserError) -> Self {
+        match err {
+            UserError::InvalidSession => Self::unauthorized(err),
+
+            UserError::InvalidActivationToken
+            | UserError::InvalidPasswordResetToken
+            | UserError::InvalidEmailChangeToken => Self::bad_request(err),
+
+            UserError::AlreadyActivated
+            | UserError::EmailTaken
+            | UserError::UsernameTaken => Self::conflict(err),
+
+            UserError::UsernameCooldown(available_at) => Self::not_yet(&err, available_at),
+
+            UserError::Session(err) => err.into(),
+            UserError::Role(err) => err.into(),
+            UserError::ApiToken(err) => err.into(),
+            UserError::Identity(err) => err.into(),
+            UserError::Denylist(err) => err.into(),
+
+            err => Self::Internal(format!("{err:#}"))
+        }
+    }
+}
+
+impl From<LoginAttemptError> for ApiError {
+    fn from(err: LoginAttemptError) -> Self {
+        match err {
+            LoginAttemptError::TooManyAttempts(retry) => Self::too_many_requests(&err, retry),
+            err => Self::Internal(format!("{err:#}"))
+        }
+    }
+}
+
+impl From<SessionError> for ApiError {
+    fn from(err: SessionError) -> Self {
+        match err {
+            SessionError::InvalidRefreshToken
+            | SessionError::RefreshTokenReused(_) => Self::unauthorized(err),
+
+            err => Self::Internal(format!("{err:#}"))
+        }
+    }
+}
+
+impl From<RoleError> for ApiError {
+    fn from(err: RoleError) -> Self {
+        match err {
+            RoleError::NotFound(_) => Self::not_found(err),
+            err => Self::Internal(format!("{err:#}"))
+        }
+    }
+}
+
+impl From<ApiTokenError> for ApiError {
+    fn from(err: ApiTokenError) -> Self {
+        match err {
+            ApiTokenError::UnknownScope(_) => Self::bad_request(err),
+            ApiTokenError::TooManyTokens => Self::conflict(err),
+            err => Self::Internal(format!("{err:#}"))
+        }
+    }
+}
+
+impl From<IdentityError> for ApiError {
+    fn from(err: IdentityError) -> Self {
+        match err {
+            IdentityError::AlreadyLinked
+            | IdentityError::ProviderLinked
+            | IdentityError::LastLoginMethod => Self::conflict(err),
+
+            err => Self::Internal(format!("{err:#}"))
+        }
+    }
+}
+
+impl From<TwoFactorError> for ApiError {
+    fn from(err: TwoFactorError) -> Self {
+        match err {
+            TwoFactorError::InvalidCode => Self::bad_request(err),
+
+            TwoFactorError::AlreadyEnabled
+            | TwoFactorError::NotEnabled => Self::conflict(err),
+
+            err => Self::Internal(format!("{err:#}"))
+        }
+    }
+}
+
+impl From<DenylistError> for ApiError {
+    fn from(err: DenylistError) -> Self {
+        match err {
+            DenylistError::InvalidTerm => Self::bad_request(err),
+            DenylistError::AlreadyDenied => Self::conflict(err),
+            err => Self::Internal(format!("{err:#}"))
+        }
+    }
+}
+
+impl From<OAuthError> for ApiError {
+    fn from(err: OAuthError) -> Self {
+        Self::Provider(format!("{err:#}"))
+    }
+}
+
+impl From<ColorError> for ApiError {
+    fn from(err: ColorError) -> Self {
+        Self::bad_request(err)
+    }
+}
+
+impl From<PositionError> for ApiError {
+    fn from(err: PositionError) -> Self {
+        Self::bad_request(err)
+    }
+}
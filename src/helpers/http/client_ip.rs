@@ -0,0 +1,22 @@
+use std::env::var_os;
+use actix_web::HttpRequest;
+use lazy_static::lazy_static;
+
+lazy_static! {
+    static ref BEHIND_PROXY: bool = var_os("BEHIND_PROXY").is_some();
+}
+
+// the forwarded headers can be set by anyone, they are only
+// trusted when BEHIND_PROXY says a proxy is overwriting them.
+pub fn client_ip(req: &HttpRequest) -> String {
+    let ip = if *BEHIND_PROXY {
+        req.connection_info()
+            .realip_remote_addr()
+            .map(String::from)
+    } else {
+        req.peer_addr()
+            .map(|addr| addr.ip().to_string())
+    };
+
+    ip.unwrap_or_else(|| "unknown".into())
+}
@@ -0,0 +1,132 @@
+use std::{env::var, ops::Add};
+use actix_web::cookie::{time::{Duration, OffsetDateTime}, Cookie, SameSite};
+use lazy_static::lazy_static;
+use crate::{helpers::oauth::provider::api_url, models::{two_factor::CHALLENGE_LIFETIME, user_session::{ACCESS_TOKEN_LIFETIME, SESSION_LIFETIME}}};
+
+pub const ACCESS_COOKIE: &str = "Session";
+pub const REFRESH_COOKIE: &str = "Refresh";
+pub const OAUTH_STATE_COOKIE: &str = "OAuthState";
+pub const OAUTH_SIGNUP_COOKIE: &str = "OAuthSignup";
+pub const TWO_FACTOR_COOKIE: &str = "TwoFactor";
+
+// the cookies that act on behalf of the user, requests carrying them
+// have to come from an allowed origin.
+pub const SESSION_COOKIES: [&str; 4] = [ACCESS_COOKIE, REFRESH_COOKIE, TWO_FACTOR_COOKIE, OAUTH_SIGNUP_COOKIE];
+
+// the refresh cookie is only sent to the auth endpoints,
+// so it doesn't travel on every request like the access one.
+const REFRESH_PATH: &str = "/auth";
+
+const OAUTH_PATH: &str = "/auth/oauth";
+
+// the settings are read from the environment of the process, so a deployment can change them without a rebuild.
+lazy_static! {
+    // COOKIE_SECURE defaults to whether the api is served over https.
+    static ref SECURE: bool = match var("COOKIE_SECURE") {
+        Ok(secure) => secure.eq_ignore_ascii_case("true"),
+        Err(_) => api_url().starts_with("https://")
+    };
+
+    // COOKIE_SAME_SITE is strict, lax or none, lax by default. with strict the session
+    // isn't sent back from the providers, so accounts can't be linked.
+    static ref SAME_SITE: SameSite = match var("COOKIE_SAME_SITE").map(|same_site| same_site.to_lowercase()).as_deref() {
+        Ok("strict") => SameSite::Strict,
+        Ok("none") => SameSite::None,
+        _ => SameSite::Lax
+    };
+
+    // COOKIE_DOMAIN shares the cookies with subdomains, by default they belong to the api host.
+    static ref DOMAIN: Option<String> = var("COOKIE_DOMAIN").ok();
+}
+
+fn secure() -> bool {
+    *SECURE
+}
+
+fn same_site() -> SameSite {
+    *SAME_SITE
+}
+
+// every cookie is http-only, the front-end reads the user from /auth/user.
+fn cookie(name: &'static str, value: String, path: &'static str, same_site: SameSite) -> Cookie<'static> {
+    let mut cookie = Cookie::new(name, value);
+
+    cookie.set_path(path);
+    cookie.set_http_only(true);
+    cookie.set_same_site(same_site);
+    // browsers drop SameSite=None cookies that aren't secure.
+    cookie.set_secure(secure() || same_site == SameSite::None);
+
+    if let Some(domain) = DOMAIN.as_deref() {
+        cookie.set_domain(domain);
+    }
+
+    cookie
+}
+
+fn expiring_cookie(name: &'static str, value: String, path: &'static str, same_site: SameSite, lifetime: Duration) -> Cookie<'static> {
+    let mut cookie = cookie(name, value, path, same_site);
+
+    cookie.set_expires(
+        OffsetDateTime::now_utc()
+            .add(lifetime)
+    );
+
+    cookie
+}
+
+fn removal_cookie(name: &'static str, path: &'static str, same_site: SameSite) -> Cookie<'static> {
+    let mut cookie = cookie(name, String::new(), path, same_site);
+    cookie.make_removal();
+
+    cookie
+}
+
+pub fn access_cookie(token: String) -> Cookie<'static> {
+    expiring_cookie(ACCESS_COOKIE, token, "/", same_site(), ACCESS_TOKEN_LIFETIME)
+}
+
+pub fn refresh_cookie(token: String) -> Cookie<'static> {
+    expiring_cookie(REFRESH_COOKIE, token, REFRESH_PATH, same_site(), SESSION_LIFETIME)
+}
+
+// the login waiting for a two-factor code.
+pub fn two_factor_cookie(challenge: String) -> Cookie<'static> {
+    expiring_cookie(TWO_FACTOR_COOKIE, challenge, REFRESH_PATH, oauth_same_site(), CHALLENGE_LIFETIME)
+}
+
+pub fn two_factor_removal_cookie() -> Cookie<'static> {
+    removal_cookie(TWO_FACTOR_COOKIE, REFRESH_PATH, oauth_same_site())
+}
+
+// cookies that make the browser forget the session.
+pub fn removal_cookies() -> [Cookie<'static>; 2] {
+    [
+        removal_cookie(ACCESS_COOKIE, "/", same_site()),
+        removal_cookie(REFRESH_COOKIE, REFRESH_PATH, same_site())
+    ]
+}
+
+// the provider redirects back with a top level navigation from its own site,
+// strict cookies wouldn't be sent with it. the two-factor challenge is set on that redirect too.
+fn oauth_same_site() -> SameSite {
+    match same_site() {
+        SameSite::Strict => SameSite::Lax,
+        same_site => same_site
+    }
+}
+
+// the state of an authorization in progress, the provider redirects back
+// with it and it's compared against this cookie.
+pub fn oauth_state_cookie(state: String) -> Cookie<'static> {
+    expiring_cookie(OAUTH_STATE_COOKIE, state, OAUTH_PATH, oauth_same_site(), Duration::minutes(10))
+}
+
+// the identity waiting for the user to choose a username.
+pub fn oauth_signup_cookie(token: String) -> Cookie<'static> {
+    expiring_cookie(OAUTH_SIGNUP_COOKIE, token, OAUTH_PATH, oauth_same_site(), Duration::minutes(30))
+}
+
+pub fn oauth_removal_cookie(name: &'static str) -> Cookie<'static> {
+    removal_cookie(name, OAUTH_PATH, oauth_same_site())
+}
@@ -0,0 +1,131 @@
+use std::{collections::HashMap, hash::Hash, sync::Mutex, time::Duration};
+use actix_web::rt::time::Instant;
+use crate::helpers::cells::position::Position;
+
+pub const CURSOR_MOVES_PER_SECOND: u32 = 15;
+
+#[derive(Default)]
+struct KeyCursor {
+    last_sent: Option<Instant>,
+    pending: Option<Position>
+}
+
+// coalesces the cursor moves of every key, a user with many sockets shares one.
+// at most CURSOR_MOVES_PER_SECOND moves are broadcast per key and everything
+// in between is replaced by the latest position.
+pub struct CursorThrottle<K> {
+    cursors: Mutex<HashMap<K, KeyCursor>>
+}
+
+impl<K: Eq + Hash> Default for CursorThrottle<K> {
+    fn default() -> Self {
+        Self {
+            cursors: Mutex::new(HashMap::new())
+        }
+    }
+}
+
+impl<K: Eq + Hash> CursorThrottle<K> {
+    fn interval() -> Duration {
+        Duration::from_secs(1) / CURSOR_MOVES_PER_SECOND
+    }
+
+    // returns the position if it can be broadcast right away,
+    // otherwise it's kept until the deadline of the key.
+    pub fn push(&self, key: K, position: Position) -> Option<Position> {
+        let now = Instant::now();
+
+        let mut cursors = self.cursors
+            .lock()
+            .unwrap_or_else(|err| err.into_inner());
+
+        cursors.retain(|_, cursor| cursor.pending.is_some() || cursor.last_sent
+            .is_some_and(|last_sent| now < last_sent + Self::interval())
+        );
+
+        let cursor = cursors
+            .entry(key)
+            .or_default();
+
+        if let Some(last_sent) = cursor.last_sent {
+            if now < last_sent + Self::interval() {
+                cursor.pending = Some(position);
+                return None;
+            }
+        }
+
+        cursor.last_sent = Some(now);
+        cursor.pending = None;
+
+        Some(position)
+    }
+
+    pub fn deadline(&self, key: &K) -> Option<Instant> {
+        let cursors = self.cursors
+            .lock()
+            .unwrap_or_else(|err| err.into_inner());
+
+        let cursor = cursors.get(key)?;
+
+        cursor.pending
+            .and(cursor.last_sent)
+            .map(|last_sent| last_sent + Self::interval())
+    }
+
+    // every socket of the key waits for the deadline, only the first one gets the position.
+    pub fn flush(&self, key: &K) -> Option<Position> {
+        let mut cursors = self.cursors
+            .lock()
+            .unwrap_or_else(|err| err.into_inner());
+
+        let cursor = cursors.get_mut(key)?;
+        let position = cursor.pending.take()?;
+
+        cursor.last_sent = Some(Instant::now());
+
+        Some(position)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::helpers::cells::position::Position;
+    use super::CursorThrottle;
+
+    fn x(position: Option<Position>) -> Option<u32> {
+        position.map(|position| position.x())
+    }
+
+    #[test]
+    fn sends_the_first_move_right_away() {
+        let throttle = CursorThrottle::default();
+
+        assert_eq!(x(throttle.push(1, Position::new(1, 1))), Some(1));
+        assert!(throttle.deadline(&1).is_none());
+    }
+
+    #[test]
+    fn keeps_only_the_latest_move_until_the_deadline() {
+        let throttle = CursorThrottle::default();
+
+        throttle.push(1, Position::new(1, 1));
+
+        assert!(throttle.push(1, Position::new(2, 2)).is_none());
+        assert!(throttle.push(1, Position::new(3, 3)).is_none());
+        assert!(throttle.deadline(&1).is_some());
+
+        assert_eq!(x(throttle.flush(&1)), Some(3));
+        assert!(throttle.deadline(&1).is_none());
+        assert!(throttle.flush(&1).is_none());
+    }
+
+    #[test]
+    fn throttles_keys_separately() {
+        let throttle = CursorThrottle::default();
+
+        throttle.push(1, Position::new(1, 1));
+
+        assert_eq!(x(throttle.push(2, Position::new(2, 2))), Some(2));
+        assert!(throttle.push(1, Position::new(3, 3)).is_none());
+    }
+}
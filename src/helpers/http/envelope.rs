@@ -0,0 +1,79 @@
+use std::{collections::BTreeMap, fmt::Display};
+use serde::Serialize;
+use time::OffsetDateTime;
+
+// errors of single fields, keyed by the name of the field.
+pub type FieldErrors = BTreeMap<&'static str, String>;
+
+// the body of every response of the api, so clients
+// can handle them all the same way.
+//
+// { "ok": true, "data": { ... } }
+// { "ok": false, "error": { "message": "...", "fields": { "username": "..." } } }
+// { "ok": false, "error": { "message": "...", "available_at": "2025-01-01T00:00:00Z" } }
+#[derive(Serialize)]
+pub struct Envelope<T: Serialize = ()> {
+    ok: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    data: Option<T>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<ErrorBody>
+}
+
+#[derive(Serialize)]
+struct ErrorBody {
+    message: String,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    fields: FieldErrors,
+    // when what was refused will be allowed.
+    #[serde(skip_serializing_if = "Option::is_none", with = "time::serde::rfc3339::option")]
+    available_at: Option<OffsetDateTime>
+}
+
+impl Envelope {
+    pub fn ok() -> Self {
+        Self {
+            ok: true,
+            data: None,
+            error: None
+        }
+    }
+
+    pub fn error(message: impl Display) -> Self {
+        Self::fields(message, FieldErrors::new())
+    }
+
+    pub fn fields(message: impl Display, fields: FieldErrors) -> Self {
+        Self {
+            ok: false,
+            data: None,
+            error: Some(ErrorBody {
+                message: message.to_string(),
+                fields,
+                available_at: None
+            })
+        }
+    }
+
+    pub fn available_at(message: impl Display, available_at: OffsetDateTime) -> Self {
+        Self {
+            ok: false,
+            data: None,
+            error: Some(ErrorBody {
+                message: message.to_string(),
+                fields: FieldErrors::new(),
+                available_at: Some(available_at)
+            })
+        }
+    }
+}
+
+impl<T: Serialize> Envelope<T> {
+    pub fn data(data: T) -> Self {
+        Self {
+            ok: true,
+            data: Some(data),
+            error: None
+        }
+    }
+}
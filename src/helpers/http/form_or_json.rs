@@ -0,0 +1,57 @@
+use std::ops::Deref;
+use actix_web::{dev::Payload, web::{Form, Json}, Error as ActixError, FromRequest, HttpMessage, HttpRequest};
+use futures_util::future::LocalBoxFuture;
+use serde::de::DeserializeOwned;
+use super::api_error::ApiError;
+
+// a body sent either as a form or as json, depending on the content type.
+// bodies that can't be read are rejected with a 400 in an envelope.
+//
+// pub async fn handler(params: FormOrJson<Params>) -> impl Responder
+pub struct FormOrJson<T>(T);
+
+impl<T> FormOrJson<T> {
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T> Deref for FormOrJson<T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl<T: DeserializeOwned + 'static> FromRequest for FormOrJson<T> {
+    type Error = ApiError;
+
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, payload: &mut Payload) -> Self::Future {
+        let content_type = req.content_type();
+
+        if content_type == "application/json" || content_type.ends_with("+json") {
+            let json = Json::<T>::from_request(req, payload);
+
+            return Box::pin(async move {
+                json.await
+                    .map(|json| Self(json.into_inner()))
+                    .map_err(invalid_body)
+            });
+        }
+
+        let form = Form::<T>::from_request(req, payload);
+
+        Box::pin(async move {
+            form.await
+                .map(|form| Self(form.into_inner()))
+                .map_err(invalid_body)
+        })
+    }
+}
+
+fn invalid_body(err: ActixError) -> ApiError {
+    ApiError::bad_request(format!("The body is not valid: {err}"))
+}
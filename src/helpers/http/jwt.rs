@@ -1,12 +1,40 @@
-use std::sync::OnceLock;
+use std::{collections::HashMap, env::var, fs::{read, read_to_string, OpenOptions}, io::{Error as IoError, Write}, os::unix::fs::OpenOptionsExt, path::Path, sync::OnceLock};
+use jsonwebtoken::{decode, decode_header, encode, errors::{Error as JwtError, ErrorKind}, Algorithm, DecodingKey, EncodingKey, Header, Validation};
 use rand::{distributions::Alphanumeric, thread_rng, Rng};
-use serde::{Deserialize, Serialize};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::Error as JsonError;
+use thiserror::Error;
+use time::OffsetDateTime;
 
-pub static JWT_HASH: OnceLock<String> = OnceLock::new();
+static KEYRING: OnceLock<Keyring> = OnceLock::new();
+
+// used when neither JWT_KEYS_FILE nor JWT_SECRET are configured,
+// the secret is generated once and kept so restarts don't log everyone out.
+const GENERATED_SECRET_FILE: &str = "jwt.key";
+
+#[derive(Error, Debug)]
+pub enum KeyringError {
+    #[error("Couldn't read a key file: {0:#}")]
+    Io(#[from] IoError),
+
+    #[error("The keys file is not valid: {0:#}")]
+    Json(#[from] JsonError),
+
+    #[error("One of the keys is not valid: {0:#}")]
+    Jwt(#[from] JwtError),
+
+    #[error("The key {0} needs a {1}.")]
+    MissingMaterial(String, &'static str),
+
+    #[error("The active key {0} is not defined or can't sign.")]
+    InvalidActiveKey(String)
+}
 
 #[derive(Serialize, Deserialize)]
 pub struct Claims<TClaims> {
     exp: usize,
+
+    #[serde(flatten)]
     claims: TClaims
 }
 
@@ -18,33 +46,227 @@ impl<T> Claims<T> {
         }
     }
 
+    pub fn expires_at(&self) -> OffsetDateTime {
+        OffsetDateTime::from_unix_timestamp(self.exp as i64)
+            .unwrap_or(OffsetDateTime::UNIX_EPOCH)
+    }
+
     pub fn into_inner(self) -> T {
         self.claims
     }
 }
 
-#[inline]
-pub fn get_jwt_hash() -> &'static [u8] {
-    JWT_HASH.get_or_init(|| {
-        thread_rng()
-            .sample_iter(&Alphanumeric)
-            .take(512)
-            .map(char::from)
-            .collect::<String>()
-    })
-        .as_ref()
+// the keys file referenced by JWT_KEYS_FILE, paths are relative to the working directory.
+//
+// {
+//     "active": "2024-12",
+//     "keys": [
+//         { "kid": "2024-12", "algorithm": "EdDSA", "private_key": "keys/2024-12.pem", "public_key": "keys/2024-12.pub.pem" },
+//         { "kid": "2024-06", "algorithm": "RS256", "public_key": "keys/2024-06.pub.pem", "verify_until": 1734652800 }
+//     ]
+// }
+//
+// only the active key signs, the rest of keys are kept to verify the tokens they signed,
+// when rotating set verify_until to when the last token signed by the old key expires.
+#[derive(Deserialize)]
+struct KeysFile {
+    active: String,
+    keys: Vec<KeyEntry>
 }
 
-#[macro_export]
-macro_rules! jwt_hash {
-    (decode) => {
-        &jsonwebtoken::DecodingKey::from_secret(
-            $crate::helpers::http::jwt::get_jwt_hash()
-        )
+#[derive(Deserialize)]
+struct KeyEntry {
+    kid: String,
+    algorithm: Algorithm,
+    secret: Option<String>,
+    private_key: Option<String>,
+    public_key: Option<String>,
+    verify_until: Option<i64>
+}
+
+struct VerifyingKey {
+    algorithm: Algorithm,
+    key: DecodingKey,
+    verify_until: Option<OffsetDateTime>
+}
+
+pub struct Keyring {
+    active: String,
+    algorithm: Algorithm,
+    signing: EncodingKey,
+    verifying: HashMap<String, VerifyingKey>
+}
+
+impl Keyring {
+    fn from_secret(kid: &str, secret: &[u8]) -> Self {
+        Self {
+            active: kid.into(),
+            algorithm: Algorithm::HS256,
+            signing: EncodingKey::from_secret(secret),
+            verifying: HashMap::from([(
+                kid.into(),
+                VerifyingKey {
+                    algorithm: Algorithm::HS256,
+                    key: DecodingKey::from_secret(secret),
+                    verify_until: None
+                }
+            )])
+        }
+    }
+
+    fn from_file(path: &str) -> Result<Self, KeyringError> {
+        let file: KeysFile = serde_json::from_str(&read_to_string(path)?)?;
+
+        let mut signing = None;
+        let mut verifying = HashMap::new();
+
+        for entry in file.keys {
+            let (encoding, decoding) = match entry.algorithm {
+                Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512 => {
+                    let secret = entry.secret
+                        .as_ref()
+                        .ok_or(KeyringError::MissingMaterial(entry.kid.clone(), "secret"))?;
+
+                    (
+                        Some(EncodingKey::from_secret(secret.as_bytes())),
+                        DecodingKey::from_secret(secret.as_bytes())
+                    )
+                },
+
+                algorithm => {
+                    let public_key = read(
+                        entry.public_key
+                            .as_ref()
+                            .ok_or(KeyringError::MissingMaterial(entry.kid.clone(), "public_key"))?
+                    )?;
+
+                    let private_key = entry.private_key
+                        .as_ref()
+                        .map(read)
+                        .transpose()?;
+
+                    match algorithm {
+                        Algorithm::EdDSA => (
+                            private_key
+                                .map(|key| EncodingKey::from_ed_pem(&key))
+                                .transpose()?,
+                            DecodingKey::from_ed_pem(&public_key)?
+                        ),
+
+                        Algorithm::ES256 | Algorithm::ES384 => (
+                            private_key
+                                .map(|key| EncodingKey::from_ec_pem(&key))
+                                .transpose()?,
+                            DecodingKey::from_ec_pem(&public_key)?
+                        ),
+
+                        _ => (
+                            private_key
+                                .map(|key| EncodingKey::from_rsa_pem(&key))
+                                .transpose()?,
+                            DecodingKey::from_rsa_pem(&public_key)?
+                        )
+                    }
+                }
+            };
+
+            if entry.kid == file.active {
+                signing = encoding.map(|key| (entry.algorithm, key));
+            }
+
+            verifying.insert(
+                entry.kid,
+                VerifyingKey {
+                    algorithm: entry.algorithm,
+                    key: decoding,
+                    verify_until: entry.verify_until
+                        .and_then(|at| OffsetDateTime::from_unix_timestamp(at).ok())
+                }
+            );
+        }
+
+        let (algorithm, signing) = signing
+            .ok_or(KeyringError::InvalidActiveKey(file.active.clone()))?;
+
+        Ok(Self {
+            active: file.active,
+            algorithm,
+            signing,
+            verifying
+        })
+    }
+
+    fn generated() -> Result<Self, KeyringError> {
+        if !Path::new(GENERATED_SECRET_FILE).exists() {
+            let secret = thread_rng()
+                .sample_iter(&Alphanumeric)
+                .take(512)
+                .map(char::from)
+                .collect::<String>();
+
+            // only the owner can read it, anyone else could sign tokens with it.
+            OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .mode(0o600)
+                .open(GENERATED_SECRET_FILE)?
+                .write_all(secret.as_bytes())?;
+        }
+
+        Ok(Self::from_secret("default", read(GENERATED_SECRET_FILE)?.as_ref()))
+    }
+}
+
+// this runs once on startup, the server won't start with an invalid keyring.
+// the keys are read from the environment of the process, not of the build,
+// so they never end up inside the binary.
+pub fn load_keyring() -> Result<(), KeyringError> {
+    let keyring = match (var("JWT_KEYS_FILE").ok(), var("JWT_SECRET").ok()) {
+        (Some(path), _) => Keyring::from_file(&path)?,
+        (None, Some(secret)) => Keyring::from_secret("default", secret.as_bytes()),
+        (None, None) => Keyring::generated()?
     };
-    (encode) => {
-        &jsonwebtoken::EncodingKey::from_secret(
-            $crate::helpers::http::jwt::get_jwt_hash()
+
+    KEYRING.get_or_init(|| keyring);
+
+    Ok(())
+}
+
+// tests sign with a fixed secret, they don't touch the environment or the key file.
+#[cfg(test)]
+pub fn load_test_keyring() {
+    KEYRING.get_or_init(|| Keyring::from_secret("test", b"canvadot tests"));
+}
+
+fn keyring() -> &'static Keyring {
+    KEYRING
+        .get()
+        .expect("The keyring is loaded on startup.")
+}
+
+pub fn encode_jwt<T: Serialize>(claims: &T) -> Result<String, JwtError> {
+    let keyring = keyring();
+
+    let mut header = Header::new(keyring.algorithm);
+    header.kid = Some(keyring.active.clone());
+
+    encode(&header, claims, &keyring.signing)
+}
+
+// tokens without a key id are verified with the active key.
+pub fn decode_jwt<T: DeserializeOwned>(token: &str) -> Result<T, JwtError> {
+    let keyring = keyring();
+
+    let kid = decode_header(token)?
+        .kid
+        .unwrap_or_else(|| keyring.active.clone());
+
+    let key = keyring.verifying
+        .get(&kid)
+        .filter(|key| key.verify_until
+            .is_none_or(|until| until > OffsetDateTime::now_utc())
         )
-    }
+        .ok_or(JwtError::from(ErrorKind::InvalidToken))?;
+
+    Ok(decode::<T>(token, &key.key, &Validation::new(key.algorithm))?.claims)
 }
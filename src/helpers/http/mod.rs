@@ -2,4 +2,14 @@
 pub mod jwt;
 pub mod socket_session;
 pub mod socket_messages;
-pub mod error_handlers;
+pub mod socket_registry;
+pub mod cursor_throttle;
+pub mod rate_limit;
+pub mod cookies;
+pub mod client_ip;
+pub mod require_role;
+pub mod require_scope;
+pub mod envelope;
+pub mod form_or_json;
+pub mod api_error;
+pub mod origin;
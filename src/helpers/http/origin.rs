@@ -0,0 +1,63 @@
+use std::{env::var, sync::OnceLock};
+use actix_web::{body::MessageBody, dev::{ServiceRequest, ServiceResponse}, http::{header::{ORIGIN, REFERER}, Method}, middleware::Next, Error as ActixError, HttpRequest};
+use url::Url;
+use crate::{helpers::{mail::templates::app_url, oauth::provider::api_url}, models::user::bearer_token};
+use super::{api_error::ApiError, cookies::SESSION_COOKIES};
+
+static ALLOWED_ORIGINS: OnceLock<Vec<String>> = OnceLock::new();
+
+// the front-end and the api itself, ALLOWED_ORIGINS adds more separated by commas.
+fn allowed_origins() -> &'static [String] {
+    ALLOWED_ORIGINS.get_or_init(|| {
+        [app_url(), api_url()]
+            .into_iter()
+            .chain(var("ALLOWED_ORIGINS").unwrap_or_default().split(','))
+            .filter_map(serialized_origin)
+            .collect()
+    })
+}
+
+// an opaque origin like "null" can't be allowed, so it's none.
+fn serialized_origin(url: &str) -> Option<String> {
+    Url::parse(url.trim())
+        .ok()
+        .map(|url| url.origin())
+        .filter(|origin| origin.is_tuple())
+        .map(|origin| origin.ascii_serialization())
+}
+
+// browsers send the origin on every cross site request that changes something and
+// on websocket upgrades, the referer is checked when it's missing. requests with
+// neither don't come from a browser, so they're only refused when they carry cookies.
+pub fn trusted_origin(req: &HttpRequest) -> bool {
+    let with_cookies = SESSION_COOKIES.iter()
+        .any(|name| req.cookie(name).is_some());
+
+    // the browser never attaches an api token on its own.
+    if !with_cookies && bearer_token(req).is_some() {
+        return true;
+    }
+
+    match req.headers().get(ORIGIN).or_else(|| req.headers().get(REFERER)) {
+        Some(origin) => origin.to_str()
+            .ok()
+            .and_then(serialized_origin)
+            .is_some_and(|origin| allowed_origins().contains(&origin)),
+
+        None => !with_cookies
+    }
+}
+
+// refuses the requests that change something from origins that aren't allowed.
+//
+// App::new().wrap(from_fn(verify_origin))
+pub async fn verify_origin(req: ServiceRequest, next: Next<impl MessageBody>) -> Result<ServiceResponse<impl MessageBody>, ActixError> {
+    let safe = matches!(*req.method(), Method::GET | Method::HEAD | Method::OPTIONS);
+
+    if !safe && !trusted_origin(req.request()) {
+        return Err(ApiError::forbidden("The request comes from an origin that is not allowed.").into());
+    }
+
+    next.call(req)
+        .await
+}
@@ -0,0 +1,85 @@
+use std::{collections::{HashMap, VecDeque}, hash::Hash, sync::Mutex, time::{Duration, Instant}};
+
+// sliding window limiter, allows `max` hits per key in every `window`.
+pub struct RateLimiter<K> {
+    max: usize,
+    window: Duration,
+    hits: Mutex<HashMap<K, VecDeque<Instant>>>
+}
+
+impl<K: Eq + Hash> RateLimiter<K> {
+    pub fn new(max: usize, window: Duration) -> Self {
+        Self {
+            max,
+            window,
+            hits: Mutex::new(HashMap::new())
+        }
+    }
+
+    // registers a hit for the key, if the key is over the limit the hit
+    // is not registered and the time left until the next allowed hit is returned.
+    pub fn hit(&self, key: K) -> Result<(), Duration> {
+        let now = Instant::now();
+
+        let mut hits = self.hits
+            .lock()
+            .unwrap_or_else(|err| err.into_inner());
+
+        hits.retain(|_, key_hits| key_hits
+            .back()
+            .is_some_and(|last| now.duration_since(*last) < self.window)
+        );
+
+        let key_hits = hits
+            .entry(key)
+            .or_default();
+
+        while key_hits
+            .front()
+            .is_some_and(|first| now.duration_since(*first) >= self.window)
+        {
+            key_hits.pop_front();
+        }
+
+        if key_hits.len() >= self.max {
+            let first = key_hits[0];
+            return Err(self.window - now.duration_since(first));
+        }
+
+        key_hits.push_back(now);
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{thread::sleep, time::Duration};
+    use super::RateLimiter;
+
+    #[test]
+    fn allows_max_hits_per_key() {
+        let limiter = RateLimiter::new(2, Duration::from_secs(60));
+
+        assert!(limiter.hit("a").is_ok());
+        assert!(limiter.hit("a").is_ok());
+
+        let retry = limiter.hit("a")
+            .expect_err("The third hit is over the limit.");
+
+        assert!(retry <= Duration::from_secs(60) && retry > Duration::from_secs(59));
+        assert!(limiter.hit("b").is_ok());
+    }
+
+    #[test]
+    fn forgets_hits_outside_the_window() {
+        let limiter = RateLimiter::new(1, Duration::from_millis(50));
+
+        assert!(limiter.hit("a").is_ok());
+        assert!(limiter.hit("a").is_err());
+
+        sleep(Duration::from_millis(60));
+
+        assert!(limiter.hit("a").is_ok());
+    }
+}
@@ -0,0 +1,80 @@
+use std::{marker::PhantomData, ops::Deref};
+use actix_web::{dev::Payload, FromRequest, HttpRequest};
+use futures_util::future::LocalBoxFuture;
+use crate::models::{role::{Role, ADMIN_ROLE, MODERATOR_ROLE}, two_factor::TwoFactor, user::SessionUser};
+use super::api_error::ApiError;
+
+pub trait RoleGuard {
+    // any of these roles lets the request through.
+    const ROLES: &'static [&'static str];
+}
+
+pub struct Admin;
+
+impl RoleGuard for Admin {
+    const ROLES: &'static [&'static str] = &[ADMIN_ROLE];
+}
+
+pub struct Moderator;
+
+impl RoleGuard for Moderator {
+    const ROLES: &'static [&'static str] = &[MODERATOR_ROLE, ADMIN_ROLE];
+}
+
+// extracts the session only if the user has one of the roles of the guard right now,
+// not the ones of the access token. otherwise the request is rejected with a 403, as it
+// is when the roles of the guard the user has require two-factor authentication and the
+// user didn't enable it, the same way sockets check their permissions.
+//
+// pub async fn handler(admin: RequireRole<Admin>) -> impl Responder
+pub struct RequireRole<R: RoleGuard> {
+    session: SessionUser,
+    guard: PhantomData<R>
+}
+
+impl<R: RoleGuard> Deref for RequireRole<R> {
+    type Target = SessionUser;
+
+    fn deref(&self) -> &Self::Target {
+        &self.session
+    }
+}
+
+impl<R: RoleGuard + 'static> FromRequest for RequireRole<R> {
+    type Error = ApiError;
+
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, payload: &mut Payload) -> Self::Future {
+        let session = SessionUser::from_request(req, payload);
+
+        Box::pin(async move {
+            let session = session
+                .await?;
+
+            let user_id = session.user().id();
+
+            let roles = Role::of_user(user_id)
+                .await?;
+
+            let guarded = |roles: &[String]| roles.iter()
+                .any(|role| R::ROLES.contains(&role.as_str()));
+
+            if !guarded(&roles) {
+                return Err(ApiError::forbidden("You don't have permission to do this."));
+            }
+
+            let usable = Role::usable(roles, TwoFactor::is_enabled(user_id).await?)
+                .await?;
+
+            if !guarded(&usable) {
+                return Err(ApiError::forbidden("Enable two-factor authentication to use your role."));
+            }
+
+            Ok(Self {
+                session,
+                guard: PhantomData
+            })
+        })
+    }
+}
@@ -0,0 +1,76 @@
+use std::{marker::PhantomData, ops::Deref};
+use actix_web::{dev::Payload, FromRequest, HttpRequest};
+use futures_util::future::LocalBoxFuture;
+use crate::models::{api_token::{ApiScope, ApiToken}, user::{bearer_token, SessionUser, UserError}};
+use super::api_error::ApiError;
+
+pub trait ScopeGuard {
+    const SCOPE: ApiScope;
+}
+
+pub struct ProfileRead;
+
+impl ScopeGuard for ProfileRead {
+    const SCOPE: ApiScope = ApiScope::ProfileRead;
+}
+
+// like SessionUser but also takes api tokens with the scope of the guard,
+// requests made with a token count towards its rate limit.
+//
+// pub async fn handler(session: RequireScope<ProfileRead>) -> impl Responder
+pub struct RequireScope<S: ScopeGuard> {
+    session: SessionUser,
+    guard: PhantomData<S>
+}
+
+impl<S: ScopeGuard> Deref for RequireScope<S> {
+    type Target = SessionUser;
+
+    fn deref(&self) -> &Self::Target {
+        &self.session
+    }
+}
+
+impl<S: ScopeGuard + 'static> FromRequest for RequireScope<S> {
+    type Error = ApiError;
+
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, payload: &mut Payload) -> Self::Future {
+        let Some(token) = bearer_token(req)
+        else {
+            let session = SessionUser::from_request(req, payload);
+
+            return Box::pin(async move {
+                Ok(Self {
+                    session: session.await?,
+                    guard: PhantomData
+                })
+            });
+        };
+
+        Box::pin(async move {
+            let session = SessionUser::from_api_token(token)
+                .await
+                .map_err(|err| match err {
+                    UserError::InvalidSession => ApiError::unauthorized("The token is not valid."),
+                    err => err.into()
+                })?;
+
+            if !session.allows(S::SCOPE) {
+                return Err(ApiError::forbidden(format!(
+                    "The token needs the {} scope for this endpoint.",
+                    S::SCOPE.as_str()
+                )));
+            }
+
+            ApiToken::hit_rate_limit(session.session_id())
+                .map_err(|retry| ApiError::too_many_requests("Too many requests with this token, try again later.", retry))?;
+
+            Ok(Self {
+                session,
+                guard: PhantomData
+            })
+        })
+    }
+}
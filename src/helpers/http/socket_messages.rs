@@ -1,4 +1,4 @@
-use crate::{helpers::cells::{color::Color, position::Position, processes::CanvasSpec}, models::user::{MaybeUser, User}};
+use crate::{helpers::cells::{color::Color, events::CellEvent, position::Position, processes::CanvasSpec}, models::{chat_message::{ChatMessage, MAX_LENGTH as CHAT_MAX_LENGTH}, online::OnlineCount, user::{MaybeUser, User}}};
 
 macro_rules! or_error {
     (r, $e:expr) => {
@@ -24,29 +24,27 @@ pub enum SocketMessage<'u> {
     WriteCell(Position, Color),
     MoveCursor(Position),
 
-    WroteCell(&'u User, Position, Color),
+    WroteCell(&'u CellEvent),
     MovedCursor(&'u User, Position),
 
     SendError(String),
 
-    InitConnection(&'u MaybeUser, CanvasSpec)
-}
+    InitConnection(&'u MaybeUser, CanvasSpec),
 
-impl<'u> SocketMessage<'u> {
-    pub fn to_sender(self, user: &'u User) -> Self {
-        match self {
-            Self::WriteCell(position, color) =>
-                Self::WroteCell(user, position, color),
+    UserJoined(&'u User),
+    UserLeft(&'u User),
+    Roster(Vec<String>),
 
-            Self::MoveCursor(position) =>
-                Self::MovedCursor(user, position),
+    OnlineCount(OnlineCount),
 
-            _ => Self::SendError(
-                "Internal message conversion error."
-                    .into()
-            )
-        }
-    }
+    SendChat(String),
+    SentChat(&'u ChatMessage),
+
+    Authenticate(String),
+    Authenticated(&'u MaybeUser),
+    SessionExpired,
+
+    ActivationRequired
 }
 
 impl<'u> From<String> for SocketMessage<'u> {
@@ -81,6 +79,22 @@ impl<'u> From<String> for SocketMessage<'u> {
                 )
             },
 
+            11 => {
+                let body = params.trim();
+
+                if body.is_empty() || body.chars().count() > CHAT_MAX_LENGTH {
+                    return Self::SendError(format!(
+                        "Chat messages must be between 1 and {CHAT_MAX_LENGTH} characters."
+                    ));
+                }
+
+                Self::SendChat(body.to_string())
+            },
+
+            13 => {
+                Self::Authenticate(params.trim().to_string())
+            },
+
             _ => {
                 Self::SendError("Invalid OP code.".into())
             }
@@ -88,33 +102,74 @@ impl<'u> From<String> for SocketMessage<'u> {
     }
 }
 
-impl<'u> Into<String> for SocketMessage<'u> {
-    fn into(self) -> String {
-        match self {
-            Self::WriteCell(pos, col)
-                => format!("1;{},{}", pos.to_string(), col.to_string()),
+impl<'u> From<SocketMessage<'u>> for String {
+    fn from(value: SocketMessage<'u>) -> Self {
+        match value {
+            SocketMessage::WriteCell(pos, col)
+                => format!("1;{},{}", pos, col),
 
-            Self::MoveCursor(pos)
-                => format!("2;{}", pos.to_string()),
+            SocketMessage::MoveCursor(pos)
+                => format!("2;{}", pos),
 
-            Self::WroteCell(user, pos, col)
-                => format!("3;{},{},{}", user.name(), pos.to_string(), col.to_string()),
+            SocketMessage::WroteCell(event)
+                => format!("3;{},{},{}", event.author(), event.position(), event.color()),
 
-            Self::MovedCursor(user, pos)
-                => format!("4;{},{}", user.name(), pos.to_string()),
+            SocketMessage::MovedCursor(user, pos)
+                => format!("4;{},{}", user.name(), pos),
 
-            Self::SendError(err)
+            SocketMessage::SendError(err)
                 => format!("5;{err}"),
 
-            Self::InitConnection(user, spec)
+            SocketMessage::InitConnection(user, spec)
                 => format!(
                     "6;{},{}",
                     match user {
                         MaybeUser::Authorized(user) => user.name(),
                         MaybeUser::Unauthorized => "null"
                     },
-                    spec.to_string()
-                )
+                    spec
+                ),
+
+            SocketMessage::UserJoined(user)
+                => format!("7;{}", user.name()),
+
+            SocketMessage::UserLeft(user)
+                => format!("8;{}", user.name()),
+
+            SocketMessage::Roster(names)
+                => format!("9;{}", names.join(",")),
+
+            SocketMessage::OnlineCount(count)
+                => format!("10;{},{}", count.users, count.viewers),
+
+            SocketMessage::SendChat(body)
+                => format!("11;{body}"),
+
+            SocketMessage::SentChat(message)
+                => format!(
+                    "12;{},{},{}",
+                    message.author(),
+                    message.created_at().unix_timestamp(),
+                    message.body()
+                ),
+
+            SocketMessage::Authenticate(token)
+                => format!("13;{token}"),
+
+            SocketMessage::Authenticated(user)
+                => format!(
+                    "14;{}",
+                    match user {
+                        MaybeUser::Authorized(user) => user.name(),
+                        MaybeUser::Unauthorized => "null"
+                    }
+                ),
+
+            SocketMessage::SessionExpired
+                => "15;".into(),
+
+            SocketMessage::ActivationRequired
+                => "16;".into()
         }
     }
 }
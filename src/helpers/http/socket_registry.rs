@@ -0,0 +1,230 @@
+use std::{collections::VecDeque, time::Duration};
+use actix_web::rt::time::interval;
+use lazy_static::lazy_static;
+use tokio::sync::{broadcast::error::RecvError, Mutex};
+use uuid::Uuid;
+use crate::{helpers::cells::events, models::{online::OnlineCount, user::{MaybeUser, SessionUser, User}}};
+use super::{socket_messages::SocketMessage, socket_session::WsSession};
+
+const ONLINE_REPORT_INTERVAL: Duration = Duration::from_secs(5);
+
+lazy_static! {
+    static ref SESSIONS: Mutex<Vec<WsSession>> = Mutex::new(Vec::new());
+}
+
+// returns true if this is the first session of an authorized user,
+// meaning the user just came online.
+pub async fn register(session: WsSession) -> bool {
+    let mut sessions = SESSIONS
+        .lock()
+        .await;
+
+    let joined = session.user_id()
+        .is_some_and(|id| !sessions
+            .iter()
+            .any(|s| s.user_id() == Some(id))
+        );
+
+    sessions.push(session);
+
+    joined
+}
+
+// returns true if this was the last session of an authorized user,
+// meaning the user just went offline.
+pub async fn unregister(session: &WsSession) -> bool {
+    let mut sessions = SESSIONS
+        .lock()
+        .await;
+
+    sessions.retain(|s| s != session);
+
+    session.user_id()
+        .is_some_and(|id| !sessions
+            .iter()
+            .any(|s| s.user_id() == Some(id))
+        )
+}
+
+// changes the user of a session, returns whether the previous user went offline
+// and whether the new user came online because of it.
+pub async fn change_user(session: &WsSession, auth: Option<SessionUser>) -> (bool, bool) {
+    let sessions = SESSIONS
+        .lock()
+        .await;
+
+    let previous = session.user_id();
+
+    session.set_auth(auth);
+
+    let current = session.user_id();
+
+    if previous == current {
+        return (false, false);
+    }
+
+    let alone = |id: i32| !sessions
+        .iter()
+        .any(|s| s != session && s.user_id() == Some(id));
+
+    (previous.is_some_and(alone), current.is_some_and(alone))
+}
+
+// closes the sockets authenticated with any of the revoked sessions, they are
+// unauthorized first so nothing else is done with them while the client hangs up.
+pub async fn close_revoked(session_ids: &[Uuid]) {
+    let mut sessions = SESSIONS
+        .lock()
+        .await;
+
+    let (revoked, kept): (Vec<_>, Vec<_>) = sessions
+        .drain(..)
+        .partition(|s| s.session_id()
+            .is_some_and(|id| session_ids.contains(&id))
+        );
+
+    *sessions = kept;
+
+    let left = gone_users(&sessions, &revoked);
+
+    for session in revoked {
+        session.set_auth(None);
+
+        session.close(Some("The session was revoked.".into()))
+            .await;
+    }
+
+    drop(sessions);
+
+    for user in left {
+        broadcast(SocketMessage::UserLeft(&user))
+            .await;
+    }
+}
+
+pub async fn roster() -> Vec<String> {
+    let mut names = SESSIONS
+        .lock()
+        .await
+        .iter()
+        .filter_map(|s| match s.user() {
+            MaybeUser::Authorized(user) => Some(user.name().clone()),
+            MaybeUser::Unauthorized => None
+        })
+        .collect::<Vec<_>>();
+
+    names.sort();
+    names.dedup();
+
+    names
+}
+
+pub async fn counts() -> OnlineCount {
+    let sessions = SESSIONS
+        .lock()
+        .await;
+
+    let mut users = sessions
+        .iter()
+        .filter_map(|s| s.user_id())
+        .collect::<Vec<_>>();
+
+    users.sort();
+    users.dedup();
+
+    OnlineCount {
+        users: users.len() as i64,
+        viewers: sessions
+            .iter()
+            .filter(|s| s.user_id().is_none())
+            .count() as i64
+    }
+}
+
+// this runs for the whole lifetime of the server, it shares the counts of this
+// instance and broadcasts the total of every instance to the connected sessions.
+pub async fn report_online_counts() {
+    let mut ticker = interval(ONLINE_REPORT_INTERVAL);
+
+    loop {
+        ticker.tick()
+            .await;
+
+        let local = counts()
+            .await;
+
+        let total = match local.publish().await {
+            Ok(()) => OnlineCount::total()
+                .await
+                .unwrap_or(local),
+            Err(_) => local
+        };
+
+        broadcast(SocketMessage::OnlineCount(total))
+            .await;
+    }
+}
+
+// this runs for the whole lifetime of the server, it relays every
+// written cell to the connected sessions.
+pub async fn forward_cell_events() {
+    let (_, mut receiver) = events::subscribe(None);
+
+    loop {
+        match receiver.recv().await {
+            Ok(event) => {
+                broadcast(SocketMessage::WroteCell(&event))
+                    .await;
+            },
+
+            Err(RecvError::Lagged(_)) => continue,
+
+            Err(RecvError::Closed) => break
+        }
+    }
+}
+
+// the users of the removed sessions that have no session left.
+fn gone_users(sessions: &[WsSession], removed: &[WsSession]) -> Vec<User> {
+    let mut left: Vec<User> = Vec::new();
+
+    for session in removed {
+        if let MaybeUser::Authorized(user) = session.user() {
+            let online = sessions
+                .iter()
+                .any(|s| s.user_id() == Some(user.id()));
+
+            if !online && !left.iter().any(|u| u.id() == user.id()) {
+                left.push(user);
+            }
+        }
+    }
+
+    left
+}
+
+// sessions that can't be written to are dropped, the others are told
+// about the users that went offline with them.
+pub async fn broadcast(message: impl Into<String>) {
+    let mut messages = VecDeque::from([message.into()]);
+
+    while let Some(message) = messages.pop_front() {
+        let mut sessions = SESSIONS
+            .lock()
+            .await;
+
+        let mut failed = Vec::new();
+
+        for session in sessions.iter_mut() {
+            if !session.text(message.as_str()).await {
+                failed.push(session.clone());
+            }
+        }
+
+        sessions.retain(|s| !failed.contains(s));
+
+        for user in gone_users(&sessions, &failed) {
+            messages.push_back(SocketMessage::UserLeft(&user).into());
+        }
+    }
+}
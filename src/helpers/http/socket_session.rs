@@ -1,22 +1,52 @@
+use std::sync::{Arc, RwLock};
 use actix_web::web::Bytes;
 use actix_ws::{CloseCode, CloseReason, Session};
+use time::OffsetDateTime;
 use uuid::Uuid;
-use crate::models::user::MaybeUser;
+use crate::models::{api_token::ApiScope, user::{MaybeUser, SessionUser}};
 
+struct SessionAuth {
+    user: MaybeUser,
+    session_id: Option<Uuid>,
+    expires_at: Option<OffsetDateTime>,
+    scopes: Option<Vec<ApiScope>>
+}
+
+impl From<Option<SessionUser>> for SessionAuth {
+    fn from(session: Option<SessionUser>) -> Self {
+        match session {
+            Some(session) => Self {
+                session_id: Some(session.session_id()),
+                expires_at: session.expires_at(),
+                scopes: session.scopes().map(<[ApiScope]>::to_vec),
+                user: MaybeUser::Authorized(session.into_user())
+            },
+
+            None => Self {
+                user: MaybeUser::Unauthorized,
+                session_id: None,
+                expires_at: None,
+                scopes: None
+            }
+        }
+    }
+}
 
+// the authentication is shared between the clones of the session,
+// so re-authenticating it is reflected everywhere the session is registered.
 #[derive(Clone)]
 pub struct WsSession {
     id: Uuid,
     session: Session,
-    user: MaybeUser
+    auth: Arc<RwLock<SessionAuth>>
 }
 
 impl WsSession {
-    pub fn new(session: Session, user: MaybeUser) -> Self {
+    pub fn new(session: Session, auth: Option<SessionUser>) -> Self {
         Self {
             id: Uuid::new_v4(),
             session,
-            user
+            auth: Arc::new(RwLock::new(auth.into()))
         }
     }
 
@@ -42,7 +72,67 @@ impl WsSession {
     }
 
     pub fn user(&self) -> MaybeUser {
-        self.user.clone()
+        self.auth
+            .read()
+            .unwrap_or_else(|err| err.into_inner())
+            .user
+            .clone()
+    }
+
+    pub fn user_id(&self) -> Option<i32> {
+        match &self.auth.read().unwrap_or_else(|err| err.into_inner()).user {
+            MaybeUser::Authorized(user) => Some(user.id()),
+            MaybeUser::Unauthorized => None
+        }
+    }
+
+    // the login session this socket was authenticated with.
+    pub fn session_id(&self) -> Option<Uuid> {
+        self.auth
+            .read()
+            .unwrap_or_else(|err| err.into_inner())
+            .session_id
+    }
+
+    pub fn expires_at(&self) -> Option<OffsetDateTime> {
+        self.auth
+            .read()
+            .unwrap_or_else(|err| err.into_inner())
+            .expires_at
+    }
+
+    pub fn allows(&self, scope: ApiScope) -> bool {
+        self.auth
+            .read()
+            .unwrap_or_else(|err| err.into_inner())
+            .scopes
+            .as_ref()
+            .is_none_or(|scopes| scopes.contains(&scope))
+    }
+
+    // the id of the api token the socket authenticated with, if it did with one.
+    pub fn api_token_id(&self) -> Option<Uuid> {
+        let auth = self.auth
+            .read()
+            .unwrap_or_else(|err| err.into_inner());
+
+        auth.scopes
+            .as_ref()
+            .and(auth.session_id)
+    }
+
+    // the session was refreshed, the socket stays authenticated until then.
+    pub fn extend(&self, expires_at: OffsetDateTime) {
+        self.auth
+            .write()
+            .unwrap_or_else(|err| err.into_inner())
+            .expires_at = Some(expires_at);
+    }
+
+    pub fn set_auth(&self, auth: Option<SessionUser>) {
+        *self.auth
+            .write()
+            .unwrap_or_else(|err| err.into_inner()) = auth.into();
     }
 }
 
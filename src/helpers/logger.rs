@@ -0,0 +1,49 @@
+use std::str::FromStr;
+use log::{set_logger, set_max_level, LevelFilter, Log, Metadata, Record};
+use time::OffsetDateTime;
+
+static LOGGER: Logger = Logger;
+
+// the dependencies are chatty, only their warnings and errors are logged.
+const DEPENDENCY_LEVEL: LevelFilter = LevelFilter::Warn;
+
+struct Logger;
+
+impl Logger {
+    fn own(target: &str) -> bool {
+        target.starts_with(env!("CARGO_CRATE_NAME"))
+    }
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        Self::own(metadata.target()) || metadata.level() <= DEPENDENCY_LEVEL
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        eprintln!(
+            "[{} {} {}] {}",
+            OffsetDateTime::now_utc(),
+            record.level(),
+            record.target(),
+            record.args()
+        );
+    }
+
+    fn flush(&self) {}
+}
+
+// logs to stderr, LOG_LEVEL sets the level of the server logs and defaults to info.
+pub fn init_logger() {
+    let level = option_env!("LOG_LEVEL")
+        .and_then(|level| LevelFilter::from_str(level).ok())
+        .unwrap_or(LevelFilter::Info);
+
+    if set_logger(&LOGGER).is_ok() {
+        set_max_level(level);
+    }
+}
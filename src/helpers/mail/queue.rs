@@ -0,0 +1,52 @@
+use std::{sync::{Arc, OnceLock}, time::Duration};
+use log::error;
+use tokio::{spawn, sync::mpsc::{unbounded_channel, UnboundedSender}, time::sleep};
+use super::mailer::{Mail, Mailer};
+
+const MAX_ATTEMPTS: u32 = 5;
+const FIRST_RETRY_DELAY: Duration = Duration::from_secs(5);
+
+static QUEUE: OnceLock<UnboundedSender<Mail>> = OnceLock::new();
+
+// this runs once on startup, mails enqueued before are dropped.
+pub fn start_mail_queue(mailer: Box<dyn Mailer>) {
+    let mailer: Arc<dyn Mailer> = Arc::from(mailer);
+    let (sender, mut receiver) = unbounded_channel::<Mail>();
+
+    QUEUE.get_or_init(|| sender);
+
+    spawn(async move {
+        while let Some(mail) = receiver.recv().await {
+            spawn(deliver(mailer.clone(), mail));
+        }
+    });
+}
+
+// requests don't wait for the mail to be delivered.
+pub fn enqueue(mail: Mail) {
+    if let Some(queue) = QUEUE.get() {
+        let _ = queue.send(mail);
+    }
+}
+
+// every failed attempt doubles the delay until the next one.
+async fn deliver(mailer: Arc<dyn Mailer>, mail: Mail) {
+    let mut delay = FIRST_RETRY_DELAY;
+
+    for attempt in 1..=MAX_ATTEMPTS {
+        let Err(err) = mailer.send(&mail).await
+        else {
+            return;
+        };
+
+        if attempt == MAX_ATTEMPTS {
+            error!("Couldn't send a mail to {} after {attempt} attempts: {err:#}", mail.to());
+            return;
+        }
+
+        sleep(delay)
+            .await;
+
+        delay *= 2;
+    }
+}
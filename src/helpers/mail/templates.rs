@@ -0,0 +1,76 @@
+use std::{env::var, sync::OnceLock};
+
+// the links point to the front-end, which calls the api with the token.
+const DEFAULT_APP_URL: &str = "http://localhost:8080";
+
+static APP_URL: OnceLock<String> = OnceLock::new();
+
+pub enum Template<'t> {
+    Activation {
+        username: &'t str,
+        token: &'t str
+    },
+
+    PasswordReset {
+        username: &'t str,
+        token: &'t str
+    },
+
+    EmailChange {
+        username: &'t str,
+        token: &'t str
+    }
+}
+
+pub fn app_url() -> &'static str {
+    APP_URL.get_or_init(|| var("APP_URL")
+        .as_deref()
+        .unwrap_or(DEFAULT_APP_URL)
+        .trim_end_matches('/')
+        .into()
+    )
+}
+
+impl Template<'_> {
+    // returns the subject and the body of the mail.
+    pub fn render(&self) -> (String, String) {
+        match self {
+            Self::Activation { username, token } => (
+                "Activate your CanvaDot account".into(),
+                format!(
+                    "Hi {username},\n\n\
+                    Welcome to CanvaDot! Open the following link to activate your account:\n\n\
+                    {}/activate?t={token}\n\n\
+                    The link expires in 2 days.\n\
+                    If you didn't create this account you can ignore this email.\n",
+                    app_url()
+                )
+            ),
+
+            Self::PasswordReset { username, token } => (
+                "Reset your CanvaDot password".into(),
+                format!(
+                    "Hi {username},\n\n\
+                    Someone asked to reset the password of your account.\n\
+                    Open the following link to choose a new one:\n\n\
+                    {}/password/reset?t={token}\n\n\
+                    The link expires in 1 hour.\n\
+                    If it wasn't you, you can ignore this email, your password won't change.\n",
+                    app_url()
+                )
+            ),
+
+            Self::EmailChange { username, token } => (
+                "Confirm your new CanvaDot email".into(),
+                format!(
+                    "Hi {username},\n\n\
+                    Open the following link to confirm this is the new email of your account:\n\n\
+                    {}/email/confirm?t={token}\n\n\
+                    The link expires in 1 day.\n\
+                    If you didn't ask for this change you can ignore this email.\n",
+                    app_url()
+                )
+            )
+        }
+    }
+}
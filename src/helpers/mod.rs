@@ -2,3 +2,10 @@
 pub mod http;
 pub mod cells;
 pub mod database;
+pub mod chat;
+pub mod tokens;
+pub mod mail;
+pub mod validation;
+pub mod oauth;
+pub mod totp;
+pub mod logger;
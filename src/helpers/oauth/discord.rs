@@ -0,0 +1,66 @@
+use std::sync::OnceLock;
+use futures_util::future::BoxFuture;
+use reqwest::Client;
+use serde::Deserialize;
+use super::provider::{ExternalIdentity, OAuthResult, Provider, ProviderConfig};
+
+static DISCORD: OnceLock<Option<Discord>> = OnceLock::new();
+
+pub struct Discord {
+    config: ProviderConfig
+}
+
+#[derive(Deserialize)]
+struct DiscordUser {
+    id: String,
+    username: String,
+    email: Option<String>,
+    #[serde(default)]
+    verified: bool
+}
+
+impl Discord {
+    pub fn configured() -> Option<&'static Self> {
+        DISCORD
+            .get_or_init(|| Some(Self {
+                config: ProviderConfig::from_env(
+                    "DISCORD",
+                    "https://discord.com/oauth2/authorize",
+                    "https://discord.com/api/oauth2/token",
+                    "https://discord.com/api",
+                    "identify email"
+                )?
+            }))
+            .as_ref()
+    }
+}
+
+impl Provider for Discord {
+    fn name(&self) -> &'static str {
+        "discord"
+    }
+
+    fn config(&self) -> &ProviderConfig {
+        &self.config
+    }
+
+    fn identity<'p>(&'p self, client: &'p Client, access_token: &'p str) -> BoxFuture<'p, OAuthResult<ExternalIdentity>> {
+        Box::pin(async move {
+            let user = client
+                .get(format!("{}/users/@me", self.config.api_url))
+                .bearer_auth(access_token)
+                .send()
+                .await?
+                .error_for_status()?
+                .json::<DiscordUser>()
+                .await?;
+
+            Ok(ExternalIdentity {
+                subject: user.id,
+                username: user.username,
+                email: user.email,
+                email_verified: user.verified
+            })
+        })
+    }
+}
@@ -0,0 +1,87 @@
+use std::sync::OnceLock;
+use futures_util::future::BoxFuture;
+use reqwest::Client;
+use serde::Deserialize;
+use super::provider::{ExternalIdentity, OAuthResult, Provider, ProviderConfig};
+
+static GITHUB: OnceLock<Option<GitHub>> = OnceLock::new();
+
+// github wants a user agent on every api request.
+const USER_AGENT: &str = "CanvaDot";
+
+pub struct GitHub {
+    config: ProviderConfig
+}
+
+#[derive(Deserialize)]
+struct GitHubUser {
+    id: u64,
+    login: String
+}
+
+#[derive(Deserialize)]
+struct GitHubEmail {
+    email: String,
+    primary: bool,
+    verified: bool
+}
+
+impl GitHub {
+    pub fn configured() -> Option<&'static Self> {
+        GITHUB
+            .get_or_init(|| Some(Self {
+                config: ProviderConfig::from_env(
+                    "GITHUB",
+                    "https://github.com/login/oauth/authorize",
+                    "https://github.com/login/oauth/access_token",
+                    "https://api.github.com",
+                    "read:user user:email"
+                )?
+            }))
+            .as_ref()
+    }
+}
+
+impl Provider for GitHub {
+    fn name(&self) -> &'static str {
+        "github"
+    }
+
+    fn config(&self) -> &ProviderConfig {
+        &self.config
+    }
+
+    // the public email of the profile may be empty, the primary one is read instead.
+    fn identity<'p>(&'p self, client: &'p Client, access_token: &'p str) -> BoxFuture<'p, OAuthResult<ExternalIdentity>> {
+        Box::pin(async move {
+            let user = client
+                .get(format!("{}/user", self.config.api_url))
+                .bearer_auth(access_token)
+                .header("User-Agent", USER_AGENT)
+                .send()
+                .await?
+                .error_for_status()?
+                .json::<GitHubUser>()
+                .await?;
+
+            let email = client
+                .get(format!("{}/user/emails", self.config.api_url))
+                .bearer_auth(access_token)
+                .header("User-Agent", USER_AGENT)
+                .send()
+                .await?
+                .error_for_status()?
+                .json::<Vec<GitHubEmail>>()
+                .await?
+                .into_iter()
+                .find(|email| email.primary);
+
+            Ok(ExternalIdentity {
+                subject: user.id.to_string(),
+                username: user.login,
+                email_verified: email.as_ref().is_some_and(|email| email.verified),
+                email: email.map(|email| email.email)
+            })
+        })
+    }
+}
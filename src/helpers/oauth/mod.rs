@@ -0,0 +1,3 @@
+pub mod provider;
+pub mod discord;
+pub mod github;
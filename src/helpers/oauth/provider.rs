@@ -0,0 +1,149 @@
+use std::{env::var, sync::OnceLock};
+use futures_util::future::BoxFuture;
+use reqwest::{Client, Error as ReqwestError, Url};
+use serde::Deserialize;
+use thiserror::Error;
+use url::ParseError;
+use super::{discord::Discord, github::GitHub};
+
+// where the api is reachable from the browser, the callbacks are built from it.
+const DEFAULT_API_URL: &str = "http://localhost:8080";
+
+static API_URL: OnceLock<String> = OnceLock::new();
+
+pub fn api_url() -> &'static str {
+    API_URL.get_or_init(|| var("API_URL")
+        .as_deref()
+        .unwrap_or(DEFAULT_API_URL)
+        .trim_end_matches('/')
+        .into()
+    )
+}
+
+#[derive(Error, Debug)]
+pub enum OAuthError {
+    #[error("{0:#}")]
+    Request(#[from] ReqwestError),
+
+    #[error("{0:#}")]
+    Url(#[from] ParseError),
+
+    #[error("The provider didn't return an access token.")]
+    MissingAccessToken
+}
+
+pub type OAuthResult<R> = Result<R, OAuthError>;
+
+// the account of the user in the provider.
+pub struct ExternalIdentity {
+    pub subject: String,
+    pub username: String,
+    pub email: Option<String>,
+    pub email_verified: bool
+}
+
+// the urls can be overridden to point at a mock authorization server.
+pub struct ProviderConfig {
+    pub client_id: String,
+    pub client_secret: String,
+    pub authorize_url: String,
+    pub token_url: String,
+    pub api_url: String,
+    pub scopes: &'static str
+}
+
+impl ProviderConfig {
+    // {PREFIX}_CLIENT_ID and {PREFIX}_CLIENT_SECRET enable the provider, {PREFIX}_AUTHORIZE_URL,
+    // {PREFIX}_TOKEN_URL and {PREFIX}_API_URL override its urls. they're read from the environment
+    // of the process, not of the build, so the secret never ends up inside the binary.
+    pub fn from_env(prefix: &str, authorize_url: &str, token_url: &str, api_url: &str, scopes: &'static str) -> Option<Self> {
+        let setting = |name: &str, default: &str| var(format!("{prefix}_{name}"))
+            .unwrap_or_else(|_| default.into());
+
+        Some(Self {
+            client_id: var(format!("{prefix}_CLIENT_ID")).ok()?,
+            client_secret: var(format!("{prefix}_CLIENT_SECRET")).ok()?,
+            authorize_url: setting("AUTHORIZE_URL", authorize_url),
+            token_url: setting("TOKEN_URL", token_url),
+            api_url: setting("API_URL", api_url),
+            scopes
+        })
+    }
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: Option<String>
+}
+
+pub trait Provider: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    fn config(&self) -> &ProviderConfig;
+
+    // reads the identity of the user from the provider api.
+    fn identity<'p>(&'p self, client: &'p Client, access_token: &'p str) -> BoxFuture<'p, OAuthResult<ExternalIdentity>>;
+
+    fn redirect_uri(&self) -> String {
+        format!(
+            "{}/auth/oauth/{}/callback",
+            api_url(),
+            self.name()
+        )
+    }
+
+    fn authorize_url(&self, state: &str) -> OAuthResult<Url> {
+        let config = self.config();
+
+        Ok(Url::parse_with_params(
+            &config.authorize_url,
+            [
+                ("response_type", "code"),
+                ("client_id", &config.client_id),
+                ("redirect_uri", &self.redirect_uri()),
+                ("scope", config.scopes),
+                ("state", state)
+            ]
+        )?)
+    }
+
+    // exchanges the code of the callback for the identity of the user.
+    fn exchange<'p>(&'p self, code: &'p str) -> BoxFuture<'p, OAuthResult<ExternalIdentity>> {
+        Box::pin(async move {
+            let config = self.config();
+            let client = Client::new();
+
+            let token = client
+                .post(&config.token_url)
+                .header("Accept", "application/json")
+                .form(&[
+                    ("grant_type", "authorization_code"),
+                    ("code", code),
+                    ("redirect_uri", &self.redirect_uri()),
+                    ("client_id", &config.client_id),
+                    ("client_secret", &config.client_secret)
+                ])
+                .send()
+                .await?
+                .error_for_status()?
+                .json::<TokenResponse>()
+                .await?
+                .access_token
+                .ok_or(OAuthError::MissingAccessToken)?;
+
+            self.identity(&client, &token)
+                .await
+        })
+    }
+}
+
+// only the providers with a client id configured are available.
+pub fn provider(name: &str) -> Option<&'static dyn Provider> {
+    match name {
+        "discord" => Discord::configured()
+            .map(|provider| provider as &dyn Provider),
+        "github" => GitHub::configured()
+            .map(|provider| provider as &dyn Provider),
+        _ => None
+    }
+}
@@ -0,0 +1,16 @@
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
+use rand::{thread_rng, RngCore};
+use sha2::{Digest, Sha256};
+
+// opaque url safe token with 256 bits of entropy.
+pub fn generate_token() -> String {
+    let mut bytes = [0u8; 32];
+    thread_rng().fill_bytes(&mut bytes);
+
+    URL_SAFE_NO_PAD.encode(bytes)
+}
+
+// tokens are only stored hashed, so a leaked table can't be used to authenticate.
+pub fn hash_token(token: &str) -> String {
+    URL_SAFE_NO_PAD.encode(Sha256::digest(token.as_bytes()))
+}
@@ -0,0 +1,207 @@
+use hmac::{Hmac, Mac};
+use rand::{distributions::Uniform, thread_rng, Rng, RngCore};
+use sha1::Sha1;
+use time::OffsetDateTime;
+
+// RFC 6238 with the parameters every authenticator app supports.
+const PERIOD: i64 = 30;
+const DIGITS: u32 = 6;
+// codes of the previous and next periods are accepted too, for clocks that drift.
+const SKEW: i64 = 1;
+
+const ISSUER: &str = "CanvaDot";
+const BASE32_ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+const RECOVERY_ALPHABET: &[u8] = b"abcdefghjkmnpqrstuvwxyz23456789";
+
+// 160 bit secret, base32 encoded like authenticator apps expect it.
+pub fn generate_secret() -> String {
+    let mut bytes = [0u8; 20];
+    thread_rng().fill_bytes(&mut bytes);
+
+    base32_encode(&bytes)
+}
+
+// what the qr code shown to the user encodes.
+pub fn provisioning_uri(secret: &str, account: &str) -> String {
+    format!(
+        "otpauth://totp/{ISSUER}:{}?secret={secret}&issuer={ISSUER}&algorithm=SHA1&digits={DIGITS}&period={PERIOD}",
+        account.replace(|c: char| !c.is_ascii_alphanumeric(), "")
+    )
+}
+
+// returns the period the code belongs to, codes of periods up to
+// the last used one are rejected so a code can't be replayed.
+pub fn verify_code(secret: &str, code: &str, last_used_step: Option<i64>) -> Option<i64> {
+    let code = code.trim();
+
+    if code.len() != DIGITS as usize || !code.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+
+    let key = base32_decode(secret)?;
+    let current = OffsetDateTime::now_utc().unix_timestamp() / PERIOD;
+
+    (current - SKEW..=current + SKEW)
+        .filter(|step| last_used_step.is_none_or(|last| *step > last))
+        .find(|step| format!("{:0width$}", code_at(&key, *step), width = DIGITS as usize) == code)
+}
+
+// like xxxxx-xxxxx, without characters that are easy to mistake.
+pub fn generate_recovery_code() -> String {
+    let mut rng = thread_rng();
+    let range = Uniform::from(0..RECOVERY_ALPHABET.len());
+
+    let mut code = (0..10)
+        .map(|_| RECOVERY_ALPHABET[rng.sample(range)] as char)
+        .collect::<String>();
+
+    code.insert(5, '-');
+
+    code
+}
+
+fn code_at(key: &[u8], step: i64) -> u32 {
+    let mut mac = Hmac::<Sha1>::new_from_slice(key)
+        .expect("Hmac accepts keys of any length.");
+
+    mac.update(&step.to_be_bytes());
+
+    let hash = mac.finalize()
+        .into_bytes();
+
+    let offset = (hash[hash.len() - 1] & 0x0f) as usize;
+    let binary = u32::from_be_bytes([hash[offset], hash[offset + 1], hash[offset + 2], hash[offset + 3]]) & 0x7fff_ffff;
+
+    binary % 10u32.pow(DIGITS)
+}
+
+fn base32_encode(bytes: &[u8]) -> String {
+    let mut encoded = String::new();
+    let mut buffer = 0u32;
+    let mut bits = 0;
+
+    for byte in bytes {
+        buffer = (buffer << 8) | *byte as u32;
+        bits += 8;
+
+        while bits >= 5 {
+            bits -= 5;
+            encoded.push(BASE32_ALPHABET[((buffer >> bits) & 0x1f) as usize] as char);
+        }
+    }
+
+    if bits > 0 {
+        encoded.push(BASE32_ALPHABET[((buffer << (5 - bits)) & 0x1f) as usize] as char);
+    }
+
+    encoded
+}
+
+fn base32_decode(encoded: &str) -> Option<Vec<u8>> {
+    let mut bytes = Vec::new();
+    let mut buffer = 0u32;
+    let mut bits = 0;
+
+    for c in encoded.trim_end_matches('=').bytes() {
+        let value = BASE32_ALPHABET.iter()
+            .position(|a| *a == c.to_ascii_uppercase())?;
+
+        buffer = (buffer << 5) | value as u32;
+        bits += 5;
+
+        if bits >= 8 {
+            bits -= 8;
+            bytes.push((buffer >> bits) as u8);
+        }
+    }
+
+    Some(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use time::OffsetDateTime;
+    use super::{base32_decode, base32_encode, code_at, generate_recovery_code, generate_secret, verify_code, DIGITS, PERIOD};
+
+    // the sha1 secret of the RFC 6238 test vectors.
+    const RFC_SECRET: &[u8] = b"12345678901234567890";
+
+    fn code(secret: &str, step: i64) -> String {
+        let key = base32_decode(secret)
+            .expect("The secret is valid base32.");
+
+        format!("{:0width$}", code_at(&key, step), width = DIGITS as usize)
+    }
+
+    fn current_step() -> i64 {
+        OffsetDateTime::now_utc().unix_timestamp() / PERIOD
+    }
+
+    #[test]
+    fn matches_the_rfc_test_vectors() {
+        // the last 6 digits of the 8 digit codes in the RFC.
+        for (time, expected) in [(59, 287082), (1111111109, 81804), (1111111111, 50471), (1234567890, 5924), (2000000000, 279037)] {
+            assert_eq!(code_at(RFC_SECRET, time / PERIOD), expected);
+        }
+    }
+
+    #[test]
+    fn base32_round_trips() {
+        let encoded = base32_encode(RFC_SECRET);
+
+        assert_eq!(encoded, "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ");
+        assert_eq!(base32_decode(&encoded).as_deref(), Some(RFC_SECRET));
+        assert_eq!(base32_decode(&encoded.to_lowercase()).as_deref(), Some(RFC_SECRET));
+        assert!(base32_decode("not base32!").is_none());
+    }
+
+    #[test]
+    fn accepts_codes_of_the_adjacent_periods() {
+        let secret = generate_secret();
+        let step = current_step();
+
+        for step in [step - 1, step, step + 1] {
+            assert_eq!(verify_code(&secret, &code(&secret, step), None), Some(step));
+        }
+    }
+
+    #[test]
+    fn rejects_codes_outside_the_window() {
+        let secret = generate_secret();
+        let step = current_step();
+
+        for step in [step - 3, step + 3] {
+            assert_eq!(verify_code(&secret, &code(&secret, step), None), None);
+        }
+    }
+
+    #[test]
+    fn rejects_replayed_codes() {
+        let secret = generate_secret();
+        let step = current_step();
+        let code = code(&secret, step);
+
+        assert_eq!(verify_code(&secret, &code, Some(step)), None);
+        assert_eq!(verify_code(&secret, &code, Some(step + 1)), None);
+        assert_eq!(verify_code(&secret, &code, Some(step - 2)), Some(step));
+    }
+
+    #[test]
+    fn rejects_malformed_codes() {
+        let secret = generate_secret();
+
+        for code in ["", "12345", "1234567", "12a456", "١٢٣٤٥٦"] {
+            assert_eq!(verify_code(&secret, code, None), None);
+        }
+    }
+
+    #[test]
+    fn generates_readable_recovery_codes() {
+        let code = generate_recovery_code();
+
+        assert_eq!(code.len(), 11);
+        assert_eq!(code.chars().nth(5), Some('-'));
+        assert!(code.chars().all(|c| c == '-' || c.is_ascii_lowercase() || c.is_ascii_digit()));
+        assert!(!code.contains(['0', '1', 'i', 'l', 'o']));
+    }
+}
@@ -0,0 +1,176 @@
+use email_address::EmailAddress;
+use unicode_normalization::UnicodeNormalization;
+use unicode_security::{skeleton, GeneralSecurityProfile, MixedScript};
+
+pub const USERNAME_MAX_LENGTH: usize = 25;
+pub const EMAIL_MAX_LENGTH: usize = 75;
+pub const PASSWORD_MIN_LENGTH: usize = 8;
+
+// bcrypt ignores everything after the first 72 bytes.
+pub const PASSWORD_MAX_BYTES: usize = 72;
+
+// names that could pass as the staff or the site, lookalikes included. they are
+// matched as the whole name, so ecosystem or rootbeer are still fine.
+const RESERVED_USERNAMES: [&str; 16] = [
+    "admin", "administrator", "moderator", "mod", "staff", "support", "system", "root",
+    "canvadot", "official", "anonymous", "deleted", "null", "undefined", "api", "help"
+];
+
+// what gets put before or after a reserved name to pass for the real one,
+// like TheAdmin or SupportTeam. reserved names count too, like CanvadotStaff.
+const RESERVED_AFFIXES: [&str; 3] = ["the", "real", "team"];
+
+// the form usernames are stored with, compatibility characters
+// like fullwidth letters become the usual ones.
+pub fn normalize_username(username: &str) -> String {
+    username.nfkc()
+        .collect()
+}
+
+// the same for names that look alike, like Admin and аdmin with a cyrillic a.
+// it's only compared, the username is shown as the user wrote it.
+pub fn canonical_username(username: &str) -> String {
+    skeleton(&normalize_username(username).to_lowercase())
+        .collect::<String>()
+        .to_lowercase()
+}
+
+// the errors are meant to be shown to the user as they are,
+// the username has to be normalized first.
+pub fn validate_username(username: &str) -> Result<(), &'static str> {
+    let length = username.chars().count();
+
+    if length == 0 || length > USERNAME_MAX_LENGTH {
+        return Err("Usernames must be between 1 and 25 characters.");
+    }
+
+    // the security profile leaves out invisible, obsolete and technical characters.
+    if username.chars().any(|c| !c.is_alphanumeric() || !c.identifier_allowed()) {
+        return Err("Username must only contain readable characters");
+    }
+
+    // mixing scripts is how names imitate others, like a latin name with a greek o.
+    if !username.is_single_script() {
+        return Err("Usernames can't mix letters of different alphabets.");
+    }
+
+    if is_reserved(username) {
+        return Err("This username is reserved.");
+    }
+
+    Ok(())
+}
+
+// the trailing digits go first, admin2 is as much the admin as admin is.
+fn is_reserved(username: &str) -> bool {
+    let canonical = canonical_username(username.trim_end_matches(|c: char| c.is_ascii_digit()));
+    let affixes = RESERVED_AFFIXES.iter()
+        .chain(RESERVED_USERNAMES.iter())
+        .map(|affix| canonical_username(affix))
+        .collect::<Vec<_>>();
+
+    RESERVED_USERNAMES.iter().any(|reserved| {
+        let reserved = canonical_username(reserved);
+
+        canonical == reserved || affixes.iter().any(|affix| {
+            canonical.strip_prefix(affix.as_str()) == Some(reserved.as_str())
+                || canonical.strip_suffix(affix.as_str()) == Some(reserved.as_str())
+        })
+    })
+}
+
+pub fn validate_email(email: &str) -> Result<(), &'static str> {
+    if email.len() > EMAIL_MAX_LENGTH {
+        return Err("Max 75 characters per email allowed.");
+    }
+
+    if !EmailAddress::is_valid(email) {
+        return Err("The email is not a valid email.");
+    }
+
+    Ok(())
+}
+
+// emails are compared and stored lowercased, so the same
+// address can't sign up twice with different casing.
+pub fn normalize_email(email: &str) -> String {
+    email.trim()
+        .to_lowercase()
+}
+
+pub fn validate_password(password: &str) -> Result<(), &'static str> {
+    if password.chars().count() < PASSWORD_MIN_LENGTH {
+        return Err("Passwords must have at least 8 characters.");
+    }
+
+    if password.len() > PASSWORD_MAX_BYTES {
+        // accented letters and emojis take more than one byte each.
+        return Err("Passwords can't be longer than 72 bytes.");
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{canonical_username, normalize_email, normalize_username, validate_password, validate_username};
+
+    #[test]
+    fn canonical_usernames_ignore_case_and_compatibility_forms() {
+        assert_eq!(canonical_username("Alice"), canonical_username("alice"));
+        assert_eq!(canonical_username("ＡＬＩＣＥ"), canonical_username("alice"));
+        assert_eq!(normalize_username("ａｌｉｃｅ"), "alice");
+    }
+
+    #[test]
+    fn canonical_usernames_match_lookalikes() {
+        // cyrillic а, greek ο and the digits that pass for letters.
+        assert_eq!(canonical_username("аdmin"), canonical_username("admin"));
+        assert_eq!(canonical_username("bοb"), canonical_username("bob"));
+        assert_eq!(canonical_username("b0b"), canonical_username("bob"));
+        assert_eq!(canonical_username("a1ice"), canonical_username("alice"));
+    }
+
+    #[test]
+    fn canonical_usernames_keep_different_names_apart() {
+        assert_ne!(canonical_username("alice"), canonical_username("alicia"));
+        assert_ne!(canonical_username("bob"), canonical_username("rob"));
+    }
+
+    #[test]
+    fn rejects_reserved_names_and_their_decorations() {
+        for username in ["admin", "Admin", "Supp0rt", "admin2", "TheAdmin", "realmoderator", "SupportTeam", "canvadotstaff"] {
+            assert_eq!(validate_username(username), Err("This username is reserved."), "{username}");
+        }
+
+        for username in ["alice", "helper", "model", "ecosystem", "Rootbeer", "badminton", "rapid"] {
+            assert_eq!(validate_username(username), Ok(()), "{username}");
+        }
+    }
+
+    #[test]
+    fn rejects_mixed_scripts_and_unreadable_characters() {
+        assert_eq!(validate_username("alicе"), Err("Usernames can't mix letters of different alphabets."));
+        assert_eq!(validate_username("аdmin"), Err("Usernames can't mix letters of different alphabets."));
+        assert!(validate_username("ali ce").is_err());
+        assert!(validate_username("alice\u{200b}").is_err());
+        assert!(validate_username("").is_err());
+        assert!(validate_username(&"a".repeat(26)).is_err());
+    }
+
+    #[test]
+    fn validates_password_lengths() {
+        assert!(validate_password("short").is_err());
+        assert!(validate_password("long enough").is_ok());
+        assert!(validate_password(&"a".repeat(72)).is_ok());
+        assert!(validate_password(&"a".repeat(73)).is_err());
+        // bcrypt counts bytes, not characters.
+        assert_eq!(validate_password(&"ñ".repeat(37)), Err("Passwords can't be longer than 72 bytes."));
+        assert!(validate_password(&"ñ".repeat(36)).is_ok());
+    }
+
+    #[test]
+    fn normalizes_emails() {
+        assert_eq!(normalize_email(" Alice@Example.COM "), "alice@example.com");
+    }
+}
@@ -1,7 +1,10 @@
-use std::io::Result as IoResult;
-use actix_web::{App, HttpServer, Scope};
-use routes::{auth::{login::login, register::register, user::user, activate::activate}, socket::session};
-use tokio::main;
+use std::{env::args, io::{Error as IoError, Result as IoResult}};
+use actix_web::{middleware::from_fn, web::{PathConfig, QueryConfig}, App, HttpServer, Scope};
+use dotenvy::dotenv;
+use helpers::{http::{api_error::invalid_request, jwt::load_keyring, origin::verify_origin, socket_registry::{forward_cell_events, report_online_counts}}, logger::init_logger, mail::{mailer::configured_mailer, queue::start_mail_queue}};
+use models::{role::Role, user::User};
+use routes::{account::{data::{delete_account, export_account}, email::{change_email, confirm_email}, identities::{list_identities, unlink_identity}, password::change_password, tokens::{create_token, list_tokens, revoke_token}, two_factor::{confirm_two_factor, disable_two_factor, enroll_two_factor, regenerate_recovery_codes, two_factor_status}, username::change_username}, auth::{login::{login, login_two_factor}, register::register, user::user, activate::{activate, resend_activation}, refresh::refresh, logout::{logout, logout_all}, password::{forgot_password, reset_password}, oauth::{oauth_authorize, oauth_callback, oauth_complete}, sessions::{list_sessions, revoke_session}}, admin::{denylist::{allow_term, denied_terms, deny_term}, roles::{grant_role, require_two_factor, revoke_role, user_roles}}, canvas::events::events, socket::session, stats::online::online, users::profile::profile};
+use tokio::{main, spawn};
 
 mod helpers;
 mod models;
@@ -9,18 +12,117 @@ mod routes;
 
 #[main]
 async fn main() -> IoResult<()> {
+    // secrets like the jwt keys are read at runtime, from the environment or the .env file.
+    dotenv().ok();
+    init_logger();
+
+    if let [_, command, email, role] = args().collect::<Vec<_>>().as_slice() {
+        if command == "grant-role" {
+            return grant_role_command(email, role)
+                .await;
+        }
+    }
+
+    load_keyring()
+        .map_err(IoError::other)?;
+
+    User::canonicalize_usernames()
+        .await
+        .map_err(IoError::other)?;
+
+    start_mail_queue(
+        configured_mailer()
+            .map_err(IoError::other)?
+    );
+
+    spawn(report_online_counts());
+    spawn(forward_cell_events());
+
     HttpServer::new(|| {
         App::new()
+            .wrap(from_fn(verify_origin))
+            .app_data(PathConfig::default().error_handler(invalid_request))
+            .app_data(QueryConfig::default().error_handler(invalid_request))
             .service(session)
             .service(
                 Scope::new("/auth")
+                    .service(login_two_factor)
                     .service(login)
                     .service(register)
                     .service(user)
+                    .service(resend_activation)
                     .service(activate)
+                    .service(refresh)
+                    .service(logout_all)
+                    .service(logout)
+                    .service(list_sessions)
+                    .service(revoke_session)
+                    .service(forgot_password)
+                    .service(reset_password)
+                    .service(oauth_complete)
+                    .service(oauth_callback)
+                    .service(oauth_authorize)
+            )
+            .service(
+                Scope::new("/account")
+                    .service(change_password)
+                    .service(change_email)
+                    .service(confirm_email)
+                    .service(change_username)
+                    .service(export_account)
+                    .service(delete_account)
+                    .service(list_tokens)
+                    .service(create_token)
+                    .service(revoke_token)
+                    .service(list_identities)
+                    .service(unlink_identity)
+                    .service(two_factor_status)
+                    .service(enroll_two_factor)
+                    .service(confirm_two_factor)
+                    .service(regenerate_recovery_codes)
+                    .service(disable_two_factor)
+            )
+            .service(
+                Scope::new("/admin")
+                    .service(user_roles)
+                    .service(grant_role)
+                    .service(revoke_role)
+                    .service(require_two_factor)
+                    .service(denied_terms)
+                    .service(deny_term)
+                    .service(allow_term)
+            )
+            .service(
+                Scope::new("/canvas")
+                    .service(events)
+            )
+            .service(
+                Scope::new("/stats")
+                    .service(online)
+            )
+            .service(
+                Scope::new("/users")
+                    .service(profile)
             )
     })
         .bind(("127.0.0.1", 8080))?
         .run()
         .await
 }
+
+// `back-end grant-role <email> <role>` gives a role to an existing user and exits,
+// it's how the first admin is created.
+async fn grant_role_command(email: &str, role: &str) -> IoResult<()> {
+    let account = User::find_by_email(email)
+        .await
+        .map_err(IoError::other)?
+        .ok_or(IoError::other("The user does not exist."))?;
+
+    Role::grant(account.id(), role)
+        .await
+        .map_err(IoError::other)?;
+
+    println!("Granted {role} to {}.", account.username());
+
+    Ok(())
+}
@@ -0,0 +1,288 @@
+use serde::Serialize;
+use sqlx::{query, query_as, Error as SqlxError};
+use thiserror::Error;
+use time::OffsetDateTime;
+use uuid::Uuid;
+use crate::{db, helpers::{cells::{color::Color, position::Position, processes::cells_by_author}, database::connection::DbConnectionError, validation::normalize_email}};
+use super::{profile::PrivateProfile, role::RoleError, user::User};
+
+#[derive(Error, Debug)]
+pub enum ExportError {
+    #[error("{0:#}")]
+    DbQuery(#[from] SqlxError),
+
+    #[error("{0:#}")]
+    DbConn(#[from] DbConnectionError),
+
+    #[error("{0:#}")]
+    Role(#[from] RoleError),
+
+    #[error("{0}")]
+    Cells(String)
+}
+
+type ExportResult<R> = Result<R, ExportError>;
+
+#[derive(Serialize)]
+struct UsernameChange {
+    old_username: String,
+    new_username: String,
+    #[serde(with = "time::serde::rfc3339")]
+    created_at: OffsetDateTime
+}
+
+#[derive(Serialize)]
+struct CreditUsage {
+    free: bool,
+    #[serde(with = "time::serde::rfc3339")]
+    created_at: OffsetDateTime
+}
+
+#[derive(Serialize)]
+struct ExportedChatMessage {
+    body: String,
+    #[serde(with = "time::serde::rfc3339")]
+    created_at: OffsetDateTime
+}
+
+#[derive(Serialize)]
+struct ExportedSession {
+    id: Uuid,
+    ip: Option<String>,
+    user_agent: Option<String>,
+    #[serde(with = "time::serde::rfc3339")]
+    created_at: OffsetDateTime,
+    #[serde(with = "time::serde::rfc3339")]
+    last_seen_at: OffsetDateTime,
+    #[serde(with = "time::serde::rfc3339")]
+    expires_at: OffsetDateTime,
+    #[serde(with = "time::serde::rfc3339::option")]
+    revoked_at: Option<OffsetDateTime>
+}
+
+#[derive(Serialize)]
+struct Identity {
+    provider: String,
+    subject: String,
+    #[serde(with = "time::serde::rfc3339")]
+    created_at: OffsetDateTime
+}
+
+// attempts are recorded by the email that was tried, the failed ones
+// may come from anyone else trying it, so their ip is left out.
+#[derive(Serialize)]
+struct ExportedLoginAttempt {
+    ip: Option<String>,
+    succeeded: bool,
+    #[serde(with = "time::serde::rfc3339")]
+    created_at: OffsetDateTime
+}
+
+#[derive(Serialize)]
+struct ExportedApiToken {
+    id: Uuid,
+    name: String,
+    scopes: Vec<String>,
+    #[serde(with = "time::serde::rfc3339")]
+    created_at: OffsetDateTime,
+    #[serde(with = "time::serde::rfc3339::option")]
+    last_used_at: Option<OffsetDateTime>,
+    #[serde(with = "time::serde::rfc3339::option")]
+    revoked_at: Option<OffsetDateTime>
+}
+
+// the secret and the recovery codes are never exported.
+#[derive(Serialize)]
+struct ExportedTwoFactor {
+    enabled: bool,
+    #[serde(with = "time::serde::rfc3339::option")]
+    enabled_at: Option<OffsetDateTime>,
+    recovery_codes_left: i64
+}
+
+#[derive(Serialize)]
+struct Pixel {
+    position: Position,
+    color: Color,
+    #[serde(with = "time::serde::rfc3339")]
+    created_at: OffsetDateTime
+}
+
+#[derive(Serialize)]
+struct VisiblePixel {
+    position: Position,
+    color: Color
+}
+
+// everything stored about a user, what GET /account/export returns.
+// pixels is every pixel painted since they started being recorded,
+// visible_pixels the ones still on the canvas, older ones included.
+#[derive(Serialize)]
+pub struct AccountExport {
+    profile: PrivateProfile,
+    two_factor: ExportedTwoFactor,
+    username_changes: Vec<UsernameChange>,
+    credit_usages: Vec<CreditUsage>,
+    chat_messages: Vec<ExportedChatMessage>,
+    sessions: Vec<ExportedSession>,
+    login_attempts: Vec<ExportedLoginAttempt>,
+    api_tokens: Vec<ExportedApiToken>,
+    identities: Vec<Identity>,
+    pixels: Vec<Pixel>,
+    visible_pixels: Vec<VisiblePixel>,
+    #[serde(with = "time::serde::rfc3339")]
+    exported_at: OffsetDateTime
+}
+
+impl AccountExport {
+    pub async fn collect(user: &User) -> ExportResult<Self> {
+        let username_changes = query_as!(
+            UsernameChange,
+            r#"
+                SELECT old_username, new_username, created_at
+                FROM username_changes
+                WHERE user_id = $1
+                ORDER BY created_at
+            "#,
+            user.id()
+        )
+            .fetch_all(db!())
+            .await?;
+
+        let credit_usages = query_as!(
+            CreditUsage,
+            r#"
+                SELECT free, created_at
+                FROM credit_usages
+                WHERE user_id = $1
+                ORDER BY created_at
+            "#,
+            user.id()
+        )
+            .fetch_all(db!())
+            .await?;
+
+        let chat_messages = query_as!(
+            ExportedChatMessage,
+            r#"
+                SELECT body, created_at
+                FROM chat_messages
+                WHERE user_id = $1
+                ORDER BY created_at
+            "#,
+            user.id()
+        )
+            .fetch_all(db!())
+            .await?;
+
+        let sessions = query_as!(
+            ExportedSession,
+            r#"
+                SELECT id, ip, user_agent, created_at, last_seen_at, expires_at, revoked_at
+                FROM sessions
+                WHERE user_id = $1
+                ORDER BY created_at
+            "#,
+            user.id()
+        )
+            .fetch_all(db!())
+            .await?;
+
+        let login_attempts = query_as!(
+            ExportedLoginAttempt,
+            r#"
+                SELECT CASE WHEN succeeded THEN ip END AS ip, succeeded, created_at
+                FROM login_attempts
+                WHERE email = $1
+                ORDER BY created_at
+            "#,
+            normalize_email(user.email())
+        )
+            .fetch_all(db!())
+            .await?;
+
+        let api_tokens = query_as!(
+            ExportedApiToken,
+            r#"
+                SELECT id, name, scopes, created_at, last_used_at, revoked_at
+                FROM api_tokens
+                WHERE user_id = $1
+                ORDER BY created_at
+            "#,
+            user.id()
+        )
+            .fetch_all(db!())
+            .await?;
+
+        let two_factor = query!(
+            r#"
+                SELECT
+                    (SELECT enabled_at FROM two_factor WHERE user_id = $1) AS enabled_at,
+                    (SELECT COUNT(*) FROM recovery_codes WHERE user_id = $1 AND used_at IS NULL) AS "recovery_codes_left!"
+            "#,
+            user.id()
+        )
+            .fetch_one(db!())
+            .await
+            .map(|two_factor| ExportedTwoFactor {
+                enabled: two_factor.enabled_at.is_some(),
+                enabled_at: two_factor.enabled_at,
+                recovery_codes_left: two_factor.recovery_codes_left
+            })?;
+
+        let identities = query_as!(
+            Identity,
+            r#"
+                SELECT provider, subject, created_at
+                FROM oauth_identities
+                WHERE user_id = $1
+                ORDER BY created_at
+            "#,
+            user.id()
+        )
+            .fetch_all(db!())
+            .await?;
+
+        let pixels = query!(
+            r#"
+                SELECT x, y, color, created_at
+                FROM painted_pixels
+                WHERE user_id = $1
+                ORDER BY id
+            "#,
+            user.id()
+        )
+            .fetch_all(db!())
+            .await?
+            .into_iter()
+            .map(|pixel| Pixel {
+                position: Position::new(pixel.x as u32, pixel.y as u32),
+                color: Color::from(pixel.color),
+                created_at: pixel.created_at
+            })
+            .collect();
+
+        let visible_pixels = cells_by_author(user.id())
+            .await
+            .map_err(ExportError::Cells)?
+            .into_iter()
+            .map(|(position, color)| VisiblePixel { position, color })
+            .collect();
+
+        Ok(Self {
+            profile: PrivateProfile::of(user)
+                .await?,
+            two_factor,
+            username_changes,
+            credit_usages,
+            chat_messages,
+            sessions,
+            login_attempts,
+            api_tokens,
+            identities,
+            pixels,
+            visible_pixels,
+            exported_at: OffsetDateTime::now_utc()
+        })
+    }
+}
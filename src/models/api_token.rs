@@ -0,0 +1,227 @@
+use std::time::Duration;
+use lazy_static::lazy_static;
+use serde::Serialize;
+use sqlx::{query, query_as, Error as SqlxError, PgExecutor};
+use thiserror::Error;
+use time::OffsetDateTime;
+use uuid::Uuid;
+use crate::{db, helpers::{database::connection::DbConnectionError, http::rate_limit::RateLimiter, tokens::{generate_token, hash_token}}};
+
+// tells api tokens apart from access tokens wherever both are accepted.
+pub const TOKEN_PREFIX: &str = "cdt_";
+
+pub const MAX_TOKENS_PER_USER: i64 = 10;
+
+lazy_static! {
+    // shared by every request and socket message made with the token.
+    static ref TOKEN_LIMITER: RateLimiter<Uuid> = RateLimiter::new(120, Duration::from_secs(60));
+}
+
+#[derive(Error, Debug)]
+pub enum ApiTokenError {
+    #[error("{0:#}")]
+    DbQuery(#[from] SqlxError),
+
+    #[error("{0:#}")]
+    DbConn(#[from] DbConnectionError),
+
+    #[error("Unknown scope {0}.")]
+    UnknownScope(String),
+
+    #[error("Max {MAX_TOKENS_PER_USER} tokens per user allowed.")]
+    TooManyTokens
+}
+
+type ApiTokenResult<R> = Result<R, ApiTokenError>;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum ApiScope {
+    ProfileRead,
+    CanvasRead,
+    CanvasWrite,
+    ChatWrite
+}
+
+impl ApiScope {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::ProfileRead => "profile:read",
+            Self::CanvasRead => "canvas:read",
+            Self::CanvasWrite => "canvas:write",
+            Self::ChatWrite => "chat:write"
+        }
+    }
+}
+
+impl TryFrom<&str> for ApiScope {
+    type Error = ApiTokenError;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        Ok(match value.trim() {
+            "profile:read" => Self::ProfileRead,
+            "canvas:read" => Self::CanvasRead,
+            "canvas:write" => Self::CanvasWrite,
+            "chat:write" => Self::ChatWrite,
+            scope => return Err(ApiTokenError::UnknownScope(scope.into()))
+        })
+    }
+}
+
+// what the owner sees about its tokens, the token itself is only shown when it's created.
+#[derive(Serialize)]
+pub struct ApiToken {
+    id: Uuid,
+    #[serde(skip)]
+    user_id: i32,
+    name: String,
+    scopes: Vec<String>,
+    #[serde(with = "time::serde::rfc3339")]
+    created_at: OffsetDateTime,
+    #[serde(with = "time::serde::rfc3339::option")]
+    last_used_at: Option<OffsetDateTime>
+}
+
+impl ApiToken {
+    pub async fn create(user_id: i32, name: &str, scopes: &[ApiScope]) -> ApiTokenResult<(Self, String)> {
+        let count = query!(
+            r#"
+                SELECT COUNT(*) AS "count!"
+                FROM api_tokens
+                WHERE user_id = $1
+                AND revoked_at IS NULL
+            "#,
+            user_id
+        )
+            .fetch_one(db!())
+            .await?
+            .count;
+
+        if count >= MAX_TOKENS_PER_USER {
+            return Err(ApiTokenError::TooManyTokens);
+        }
+
+        let token = format!("{TOKEN_PREFIX}{}", generate_token());
+
+        let scopes = scopes
+            .iter()
+            .map(|scope| scope.as_str().to_string())
+            .collect::<Vec<_>>();
+
+        let created = query_as!(
+            Self,
+            r#"
+                INSERT INTO api_tokens (id, user_id, name, token_hash, scopes)
+                VALUES ($1, $2, $3, $4, $5)
+                RETURNING id, user_id, name, scopes, created_at, last_used_at
+            "#,
+            Uuid::new_v4(),
+            user_id,
+            name,
+            hash_token(&token),
+            &scopes
+        )
+            .fetch_one(db!())
+            .await?;
+
+        Ok((created, token))
+    }
+
+    pub async fn list(user_id: i32) -> ApiTokenResult<Vec<Self>> {
+        Ok(
+            query_as!(
+                Self,
+                r#"
+                    SELECT id, user_id, name, scopes, created_at, last_used_at
+                    FROM api_tokens
+                    WHERE user_id = $1
+                    AND revoked_at IS NULL
+                    ORDER BY created_at
+                "#,
+                user_id
+            )
+                .fetch_all(db!())
+                .await?
+        )
+    }
+
+    // returns whether the user had a token with that id.
+    pub async fn revoke(user_id: i32, id: Uuid) -> ApiTokenResult<bool> {
+        Ok(
+            query!(
+                r#"
+                    UPDATE api_tokens
+                    SET revoked_at = NOW()
+                    WHERE id = $1
+                    AND user_id = $2
+                    AND revoked_at IS NULL
+                "#,
+                id,
+                user_id
+            )
+                .execute(db!())
+                .await?
+                .rows_affected() > 0
+        )
+    }
+
+    // returns the ids of the tokens that were revoked.
+    pub async fn revoke_all(executor: impl PgExecutor<'_>, user_id: i32) -> ApiTokenResult<Vec<Uuid>> {
+        Ok(
+            query!(
+                r#"
+                    UPDATE api_tokens
+                    SET revoked_at = NOW()
+                    WHERE user_id = $1
+                    AND revoked_at IS NULL
+                    RETURNING id
+                "#,
+                user_id
+            )
+                .fetch_all(executor)
+                .await?
+                .into_iter()
+                .map(|token| token.id)
+                .collect()
+        )
+    }
+
+    // finds the token and marks it as used.
+    pub async fn authenticate(token: &str) -> ApiTokenResult<Option<Self>> {
+        Ok(
+            query_as!(
+                Self,
+                r#"
+                    UPDATE api_tokens
+                    SET last_used_at = NOW()
+                    WHERE token_hash = $1
+                    AND revoked_at IS NULL
+                    RETURNING id, user_id, name, scopes, created_at, last_used_at
+                "#,
+                hash_token(token)
+            )
+                .fetch_optional(db!())
+                .await?
+        )
+    }
+
+    // registers a use of the token, returns how long to wait if it's over its limit.
+    pub fn hit_rate_limit(id: Uuid) -> Result<(), Duration> {
+        TOKEN_LIMITER.hit(id)
+    }
+
+    pub fn id(&self) -> Uuid {
+        self.id
+    }
+
+    pub fn user_id(&self) -> i32 {
+        self.user_id
+    }
+
+    // scopes that stopped existing are ignored.
+    pub fn scopes(&self) -> Vec<ApiScope> {
+        self.scopes
+            .iter()
+            .filter_map(|scope| ApiScope::try_from(scope.as_str()).ok())
+            .collect()
+    }
+}
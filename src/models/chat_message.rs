@@ -0,0 +1,82 @@
+use sqlx::{query, query_as, Error as SqlxError};
+use thiserror::Error;
+use time::OffsetDateTime;
+use crate::{db, helpers::database::connection::DbConnectionError};
+use super::user::User;
+
+pub const MAX_LENGTH: usize = 256;
+pub const HISTORY_SIZE: i64 = 50;
+
+#[derive(Error, Debug)]
+pub enum ChatError {
+    #[error("{0:#}")]
+    DbQuery(#[from] SqlxError),
+
+    #[error("{0:#}")]
+    DbConn(#[from] DbConnectionError)
+}
+
+type ChatResult<R> = Result<R, ChatError>;
+
+#[derive(Clone)]
+pub struct ChatMessage {
+    author: String,
+    body: String,
+    created_at: OffsetDateTime
+}
+
+impl ChatMessage {
+    pub async fn insert(author: &User, body: String) -> ChatResult<Self> {
+        let created_at = query!(
+            r#"
+                INSERT INTO chat_messages (user_id, body)
+                VALUES ($1, $2)
+                RETURNING created_at
+            "#,
+            author.id(),
+            body
+        )
+            .fetch_one(db!())
+            .await?
+            .created_at;
+
+        Ok(Self {
+            author: author.name().clone(),
+            body,
+            created_at
+        })
+    }
+
+    // the last HISTORY_SIZE messages, oldest first.
+    pub async fn history() -> ChatResult<Vec<Self>> {
+        let mut messages = query_as!(
+            Self,
+            r#"
+                SELECT u.username AS author, m.body, m.created_at
+                FROM chat_messages m
+                JOIN users u ON u.id = m.user_id
+                ORDER BY m.id DESC
+                LIMIT $1
+            "#,
+            HISTORY_SIZE
+        )
+            .fetch_all(db!())
+            .await?;
+
+        messages.reverse();
+
+        Ok(messages)
+    }
+
+    pub fn author(&self) -> &String {
+        &self.author
+    }
+
+    pub fn body(&self) -> &String {
+        &self.body
+    }
+
+    pub fn created_at(&self) -> OffsetDateTime {
+        self.created_at
+    }
+}
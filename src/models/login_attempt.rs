@@ -0,0 +1,226 @@
+use std::time::Duration as StdDuration;
+use sqlx::{query, Error as SqlxError};
+use thiserror::Error;
+use time::{Duration, OffsetDateTime};
+use crate::{db, helpers::{database::connection::DbConnectionError, validation::normalize_email}};
+
+// failures allowed before the backoff starts.
+const ACCOUNT_FREE_ATTEMPTS: i64 = 3;
+const IP_FREE_ATTEMPTS: i64 = 10;
+
+// failures after which the account is locked instead of backed off.
+const LOCKOUT_ATTEMPTS: i64 = 10;
+const LOCKOUT_DURATION: Duration = Duration::minutes(15);
+
+// failures older than this are forgiven.
+const ATTEMPTS_WINDOW: Duration = Duration::hours(1);
+
+#[derive(Error, Debug)]
+pub enum LoginAttemptError {
+    #[error("{0:#}")]
+    DbQuery(#[from] SqlxError),
+
+    #[error("{0:#}")]
+    DbConn(#[from] DbConnectionError),
+
+    #[error("Too many failed attempts, try again later.")]
+    TooManyAttempts(StdDuration)
+}
+
+type LoginAttemptResult<R> = Result<R, LoginAttemptError>;
+
+// every login is recorded, the failures of the account since its last success
+// and the recent failures of the ip decide how long the next attempt has to wait.
+pub struct LoginAttempt {
+    id: i64
+}
+
+struct Failures {
+    count: i64,
+    last: Option<OffsetDateTime>
+}
+
+impl Failures {
+    fn retry_after(&self, free_attempts: i64, lockout: bool) -> Option<StdDuration> {
+        let last = self.last?;
+
+        let delay = if lockout && self.count >= LOCKOUT_ATTEMPTS {
+            LOCKOUT_DURATION
+        } else if self.count >= free_attempts {
+            Duration::seconds(2_i64.pow((self.count - free_attempts).min(10) as u32))
+                .min(LOCKOUT_DURATION)
+        } else {
+            return None;
+        };
+
+        StdDuration::try_from(last + delay - OffsetDateTime::now_utc())
+            .ok()
+            .filter(|wait| !wait.is_zero())
+    }
+}
+
+impl LoginAttempt {
+    // registers the attempt as failed unless the client has to wait, checked before
+    // verifying the password so it can't be used to burn cpu either. the attempts of
+    // the account and the ip are locked meanwhile, so concurrent logins can't all
+    // get past the check before any of them is registered.
+    pub async fn start(email: &str, ip: &str) -> LoginAttemptResult<Self> {
+        let email = normalize_email(email);
+        let since = OffsetDateTime::now_utc() - ATTEMPTS_WINDOW;
+
+        let mut tx = db!()
+            .begin()
+            .await?;
+
+        query!(
+            r#"
+                SELECT COUNT(*)
+                FROM (
+                    SELECT pg_advisory_xact_lock(1, hashtext($1)), pg_advisory_xact_lock(2, hashtext($2))
+                ) locks
+            "#,
+            email,
+            ip
+        )
+            .fetch_one(&mut *tx)
+            .await?;
+
+        let account = query!(
+            r#"
+                SELECT COUNT(*) AS "count!", MAX(created_at) AS last
+                FROM login_attempts
+                WHERE email = $1
+                AND NOT succeeded
+                AND created_at > GREATEST($2, (
+                    SELECT MAX(created_at)
+                    FROM login_attempts
+                    WHERE email = $1
+                    AND succeeded
+                ))
+            "#,
+            email,
+            since
+        )
+            .fetch_one(&mut *tx)
+            .await?;
+
+        let ip_failures = query!(
+            r#"
+                SELECT COUNT(*) AS "count!", MAX(created_at) AS last
+                FROM login_attempts
+                WHERE ip = $1
+                AND NOT succeeded
+                AND created_at > $2
+            "#,
+            ip,
+            since
+        )
+            .fetch_one(&mut *tx)
+            .await?;
+
+        let account = Failures { count: account.count, last: account.last }
+            .retry_after(ACCOUNT_FREE_ATTEMPTS, true);
+
+        let ip_failures = Failures { count: ip_failures.count, last: ip_failures.last }
+            .retry_after(IP_FREE_ATTEMPTS, false);
+
+        if let Some(retry) = account.max(ip_failures) {
+            return Err(LoginAttemptError::TooManyAttempts(retry));
+        }
+
+        let id = query!(
+            r#"
+                INSERT INTO login_attempts (email, ip, succeeded)
+                VALUES ($1, $2, FALSE)
+                RETURNING id
+            "#,
+            email,
+            ip
+        )
+            .fetch_one(&mut *tx)
+            .await?
+            .id;
+
+        tx.commit()
+            .await?;
+
+        Ok(Self { id })
+    }
+
+    // the attempt stays failed unless the login finishes.
+    pub async fn succeed(self) -> LoginAttemptResult<()> {
+        query!(
+            r#"
+                UPDATE login_attempts
+                SET succeeded = TRUE
+                WHERE id = $1
+            "#,
+            self.id
+        )
+            .execute(db!())
+            .await?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration as StdDuration;
+    use time::{Duration, OffsetDateTime};
+    use super::{Failures, ACCOUNT_FREE_ATTEMPTS, LOCKOUT_ATTEMPTS, LOCKOUT_DURATION};
+
+    fn failures(count: i64, ago: Duration) -> Failures {
+        Failures {
+            count,
+            last: Some(OffsetDateTime::now_utc() - ago)
+        }
+    }
+
+    // the wait is measured from now, so it's a bit under the delay.
+    fn waits(retry_after: Option<StdDuration>, delay: Duration) -> bool {
+        retry_after.is_some_and(|wait| wait <= delay && wait > delay - Duration::seconds(1))
+    }
+
+    #[test]
+    fn allows_the_free_attempts() {
+        assert!(failures(ACCOUNT_FREE_ATTEMPTS - 1, Duration::ZERO).retry_after(ACCOUNT_FREE_ATTEMPTS, true).is_none());
+
+        let none = Failures { count: 0, last: None };
+        assert!(none.retry_after(ACCOUNT_FREE_ATTEMPTS, true).is_none());
+    }
+
+    #[test]
+    fn doubles_the_delay_after_the_free_attempts() {
+        for extra in 0..4 {
+            let retry_after = failures(ACCOUNT_FREE_ATTEMPTS + extra, Duration::ZERO)
+                .retry_after(ACCOUNT_FREE_ATTEMPTS, true);
+
+            assert!(waits(retry_after, Duration::seconds(2_i64.pow(extra as u32))));
+        }
+    }
+
+    #[test]
+    fn locks_the_account_out() {
+        let retry_after = failures(LOCKOUT_ATTEMPTS, Duration::ZERO)
+            .retry_after(ACCOUNT_FREE_ATTEMPTS, true);
+
+        assert!(waits(retry_after, LOCKOUT_DURATION));
+    }
+
+    #[test]
+    fn caps_the_delay_without_lockout() {
+        let retry_after = failures(100, Duration::ZERO)
+            .retry_after(ACCOUNT_FREE_ATTEMPTS, false);
+
+        assert!(waits(retry_after, LOCKOUT_DURATION));
+    }
+
+    #[test]
+    fn forgets_delays_that_passed() {
+        let retry_after = failures(LOCKOUT_ATTEMPTS, LOCKOUT_DURATION + Duration::seconds(1))
+            .retry_after(ACCOUNT_FREE_ATTEMPTS, true);
+
+        assert!(retry_after.is_none());
+    }
+}
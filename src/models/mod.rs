@@ -1,2 +1,14 @@
 
 pub mod user;
+pub mod online;
+pub mod chat_message;
+pub mod profile;
+pub mod user_session;
+pub mod user_token;
+pub mod login_attempt;
+pub mod account_export;
+pub mod role;
+pub mod api_token;
+pub mod oauth_identity;
+pub mod two_factor;
+pub mod username_denylist;
@@ -0,0 +1,132 @@
+use serde::Serialize;
+use sqlx::{query, query_as, Error as SqlxError};
+use thiserror::Error;
+use time::OffsetDateTime;
+use crate::{db, helpers::database::connection::DbConnectionError};
+
+#[derive(Error, Debug)]
+pub enum IdentityError {
+    #[error("{0:#}")]
+    DbQuery(#[from] SqlxError),
+
+    #[error("{0:#}")]
+    DbConn(#[from] DbConnectionError),
+
+    #[error("This account is already linked to another user.")]
+    AlreadyLinked,
+
+    #[error("Another account of this provider is already linked.")]
+    ProviderLinked,
+
+    #[error("Set a password or link another account before unlinking this one.")]
+    LastLoginMethod
+}
+
+type IdentityResult<R> = Result<R, IdentityError>;
+
+// an account of an external provider the user can log in with,
+// a user can link one account per provider.
+#[derive(Serialize)]
+pub struct OAuthIdentity {
+    provider: String,
+    #[serde(skip)]
+    user_id: i32,
+    #[serde(with = "time::serde::rfc3339")]
+    created_at: OffsetDateTime
+}
+
+impl OAuthIdentity {
+    pub async fn find(provider: &str, subject: &str) -> IdentityResult<Option<Self>> {
+        Ok(query_as!(
+            Self,
+            r#"
+                SELECT provider, user_id, created_at
+                FROM oauth_identities
+                WHERE provider = $1
+                AND subject = $2
+            "#,
+            provider,
+            subject
+        )
+            .fetch_optional(db!())
+            .await?)
+    }
+
+    pub async fn link(user_id: i32, provider: &str, subject: &str) -> IdentityResult<()> {
+        let linked = query!(
+            r#"
+                INSERT INTO oauth_identities (provider, subject, user_id)
+                VALUES ($1, $2, $3)
+                ON CONFLICT (provider, subject) DO NOTHING
+            "#,
+            provider,
+            subject,
+            user_id
+        )
+            .execute(db!())
+            .await
+            .map_err(|err| match err.as_database_error() {
+                Some(db_err) if db_err.is_unique_violation() => IdentityError::ProviderLinked,
+                _ => IdentityError::DbQuery(err)
+            })?
+            .rows_affected();
+
+        if linked == 0 {
+            // linking the same account twice is not an error.
+            return match Self::find(provider, subject).await? {
+                Some(identity) if identity.user_id == user_id => Ok(()),
+                _ => Err(IdentityError::AlreadyLinked)
+            };
+        }
+
+        Ok(())
+    }
+
+    pub async fn of_user(user_id: i32) -> IdentityResult<Vec<Self>> {
+        Ok(query_as!(
+            Self,
+            r#"
+                SELECT provider, user_id, created_at
+                FROM oauth_identities
+                WHERE user_id = $1
+                ORDER BY created_at
+            "#,
+            user_id
+        )
+            .fetch_all(db!())
+            .await?)
+    }
+
+    // a user without a password needs at least one identity left to log in with,
+    // returns whether there was an identity to unlink.
+    pub async fn unlink(user_id: i32, provider: &str, has_password: bool) -> IdentityResult<bool> {
+        let identities = Self::of_user(user_id)
+            .await?;
+
+        if !identities.iter().any(|identity| identity.provider == provider) {
+            return Ok(false);
+        }
+
+        if !has_password && identities.len() < 2 {
+            return Err(IdentityError::LastLoginMethod);
+        }
+
+        query!(
+            r#"
+                DELETE FROM oauth_identities
+                WHERE user_id = $1
+                AND provider = $2
+            "#,
+            user_id,
+            provider
+        )
+            .execute(db!())
+            .await?;
+
+        Ok(true)
+    }
+
+    pub fn user_id(&self) -> i32 {
+        self.user_id
+    }
+}
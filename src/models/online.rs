@@ -0,0 +1,77 @@
+use lazy_static::lazy_static;
+use serde::Serialize;
+use sqlx::{query, query_as, Error as SqlxError};
+use thiserror::Error;
+use uuid::Uuid;
+use crate::{db, helpers::database::connection::DbConnectionError};
+
+lazy_static! {
+    static ref INSTANCE_ID: Uuid = Uuid::new_v4();
+}
+
+#[derive(Error, Debug)]
+pub enum OnlineError {
+    #[error("{0:#}")]
+    DbQuery(#[from] SqlxError),
+
+    #[error("{0:#}")]
+    DbConn(#[from] DbConnectionError)
+}
+
+type OnlineResult<R> = Result<R, OnlineError>;
+
+// users are authorized users connected to the canvas, viewers are the
+// unauthorized sessions watching it.
+#[derive(Serialize, Clone, Copy, Default)]
+pub struct OnlineCount {
+    pub users: i64,
+    pub viewers: i64
+}
+
+impl OnlineCount {
+    // stores the counts of this instance so the rest of instances can
+    // sum them up, instances that stop reporting are dropped after a minute.
+    pub async fn publish(&self) -> OnlineResult<()> {
+        query!(
+            r#"
+                INSERT INTO online_instances (id, users, viewers)
+                VALUES ($1, $2, $3)
+                ON CONFLICT (id) DO UPDATE
+                SET users = $2,
+                    viewers = $3,
+                    updated_at = NOW()
+            "#,
+            *INSTANCE_ID,
+            self.users as i32,
+            self.viewers as i32
+        )
+            .execute(db!())
+            .await?;
+
+        query!(
+            r#"
+                DELETE FROM online_instances
+                WHERE updated_at < NOW() - INTERVAL '1 minute'
+            "#
+        )
+            .execute(db!())
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn total() -> OnlineResult<Self> {
+        Ok(query_as!(
+            Self,
+            r#"
+                SELECT
+                    COALESCE(SUM(users), 0) AS "users!",
+                    COALESCE(SUM(viewers), 0) AS "viewers!"
+                FROM online_instances
+                WHERE updated_at >= NOW() - INTERVAL '15 seconds'
+            "#
+        )
+            .fetch_one(db!())
+            .await?)
+    }
+}
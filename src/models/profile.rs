@@ -0,0 +1,56 @@
+use serde::Serialize;
+use time::OffsetDateTime;
+use super::{role::{Role, RoleError}, user::User};
+
+// what the user can see about itself.
+#[derive(Serialize)]
+pub struct PrivateProfile {
+    id: i32,
+    username: String,
+    email: String,
+    credits: i32,
+    #[serde(with = "time::serde::rfc3339")]
+    next_free_credit: OffsetDateTime,
+    activated: bool,
+    #[serde(with = "time::serde::rfc3339")]
+    created_at: OffsetDateTime,
+    pixels_placed: i32,
+    roles: Vec<String>
+}
+
+// what anyone can see about a user.
+#[derive(Serialize)]
+pub struct PublicProfile {
+    username: String,
+    #[serde(with = "time::serde::rfc3339")]
+    created_at: OffsetDateTime,
+    pixels_placed: i32
+}
+
+impl PrivateProfile {
+    // the roles are the current ones, not the ones in the access token.
+    pub async fn of(user: &User) -> Result<Self, RoleError> {
+        Ok(Self {
+            id: user.id(),
+            username: user.username().into(),
+            email: user.email().into(),
+            credits: user.credits(),
+            next_free_credit: user.next_free_credit(),
+            activated: user.activated(),
+            created_at: user.created_at(),
+            pixels_placed: user.pixels_placed(),
+            roles: Role::of_user(user.id())
+                .await?
+        })
+    }
+}
+
+impl From<&User> for PublicProfile {
+    fn from(user: &User) -> Self {
+        Self {
+            username: user.username().into(),
+            created_at: user.created_at(),
+            pixels_placed: user.pixels_placed()
+        }
+    }
+}
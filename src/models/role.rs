@@ -0,0 +1,254 @@
+use std::{collections::HashSet, slice, time::Duration};
+use lazy_static::lazy_static;
+use sqlx::{query, Error as SqlxError};
+use thiserror::Error;
+use crate::{db, helpers::database::{cache::Cache, connection::DbConnectionError}};
+
+// every user gets this role when registering, removing it takes away painting and chatting.
+pub const DEFAULT_ROLE: &str = "user";
+pub const MODERATOR_ROLE: &str = "moderator";
+pub const ADMIN_ROLE: &str = "admin";
+
+lazy_static! {
+    static ref PERMISSIONS_CACHE: Cache<String, HashSet<Permission>> = Cache::new(Duration::from_secs(30));
+    static ref TWO_FACTOR_CACHE: Cache<String, bool> = Cache::new(Duration::from_secs(30));
+    static ref USER_ROLES_CACHE: Cache<i32, Vec<String>> = Cache::new(Duration::from_secs(5));
+}
+
+#[derive(Error, Debug)]
+pub enum RoleError {
+    #[error("{0:#}")]
+    DbQuery(#[from] SqlxError),
+
+    #[error("{0:#}")]
+    DbConn(#[from] DbConnectionError),
+
+    #[error("The role {0} does not exist.")]
+    NotFound(String)
+}
+
+type RoleResult<R> = Result<R, RoleError>;
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Permission {
+    Paint,
+    Chat,
+    ModerateChat,
+    ManageUsers,
+    ManageRoles
+}
+
+impl Permission {
+    fn parse(permission: &str) -> Option<Self> {
+        Some(match permission {
+            "paint" => Self::Paint,
+            "chat" => Self::Chat,
+            "moderate_chat" => Self::ModerateChat,
+            "manage_users" => Self::ManageUsers,
+            "manage_roles" => Self::ManageRoles,
+            _ => return None
+        })
+    }
+}
+
+pub struct Role;
+
+impl Role {
+    // the roles of the user right now, unlike the ones in its access token.
+    pub async fn of_user(user_id: i32) -> RoleResult<Vec<String>> {
+        if let Some(roles) = USER_ROLES_CACHE.get(&user_id) {
+            return Ok(roles);
+        }
+
+        let roles = query!(
+            r#"
+                SELECT r.name
+                FROM user_roles ur
+                JOIN roles r ON r.id = ur.role_id
+                WHERE ur.user_id = $1
+                ORDER BY r.name
+            "#,
+            user_id
+        )
+            .fetch_all(db!())
+            .await?
+            .into_iter()
+            .map(|role| role.name)
+            .collect::<Vec<_>>();
+
+        USER_ROLES_CACHE.insert(user_id, roles.clone());
+
+        Ok(roles)
+    }
+
+    // the union of the permissions of every role.
+    pub async fn permissions(roles: &[String]) -> RoleResult<HashSet<Permission>> {
+        let mut permissions = HashSet::new();
+
+        for role in roles {
+            if let Some(cached) = PERMISSIONS_CACHE.get(role) {
+                permissions.extend(cached);
+                continue;
+            }
+
+            let granted = query!(
+                r#"
+                    SELECT rp.permission
+                    FROM role_permissions rp
+                    JOIN roles r ON r.id = rp.role_id
+                    WHERE r.name = $1
+                "#,
+                role
+            )
+                .fetch_all(db!())
+                .await?
+                .into_iter()
+                .filter_map(|row| Permission::parse(&row.permission))
+                .collect::<HashSet<_>>();
+
+            PERMISSIONS_CACHE.insert(role.clone(), granted.clone());
+            permissions.extend(granted);
+        }
+
+        Ok(permissions)
+    }
+
+    // whether any of the roles can only be used with two-factor authentication enabled.
+    pub async fn require_two_factor(roles: &[String]) -> RoleResult<bool> {
+        for role in roles {
+            let required = match TWO_FACTOR_CACHE.get(role) {
+                Some(required) => required,
+                None => {
+                    let required = query!(
+                        r#"
+                            SELECT requires_two_factor
+                            FROM roles
+                            WHERE name = $1
+                        "#,
+                        role
+                    )
+                        .fetch_optional(db!())
+                        .await?
+                        .is_some_and(|row| row.requires_two_factor);
+
+                    TWO_FACTOR_CACHE.insert(role.clone(), required);
+
+                    required
+                }
+            };
+
+            if required {
+                return Ok(true);
+            }
+        }
+
+        Ok(false)
+    }
+
+    // the roles that can be used, the ones that require two-factor authentication
+    // don't count until the user enables it, the rest of roles keep working.
+    pub async fn usable(roles: Vec<String>, two_factor_enabled: bool) -> RoleResult<Vec<String>> {
+        if two_factor_enabled {
+            return Ok(roles);
+        }
+
+        let mut usable = Vec::with_capacity(roles.len());
+
+        for role in roles {
+            if !Self::require_two_factor(slice::from_ref(&role)).await? {
+                usable.push(role);
+            }
+        }
+
+        Ok(usable)
+    }
+
+    pub async fn set_requires_two_factor(role: &str, required: bool) -> RoleResult<()> {
+        let updated = query!(
+            r#"
+                UPDATE roles
+                SET requires_two_factor = $2
+                WHERE name = $1
+            "#,
+            role,
+            required
+        )
+            .execute(db!())
+            .await?
+            .rows_affected();
+
+        if updated == 0 {
+            return Err(RoleError::NotFound(role.into()));
+        }
+
+        TWO_FACTOR_CACHE.invalidate(&role.to_string());
+
+        Ok(())
+    }
+
+    pub async fn grant(user_id: i32, role: &str) -> RoleResult<()> {
+        let granted = query!(
+            r#"
+                INSERT INTO user_roles (user_id, role_id)
+                SELECT $1, id
+                FROM roles
+                WHERE name = $2
+                ON CONFLICT DO NOTHING
+                RETURNING role_id
+            "#,
+            user_id,
+            role
+        )
+            .fetch_optional(db!())
+            .await?;
+
+        if granted.is_none() && !Self::exists(role).await? {
+            return Err(RoleError::NotFound(role.into()));
+        }
+
+        USER_ROLES_CACHE.invalidate(&user_id);
+
+        Ok(())
+    }
+
+    pub async fn revoke(user_id: i32, role: &str) -> RoleResult<()> {
+        query!(
+            r#"
+                DELETE FROM user_roles
+                WHERE user_id = $1
+                AND role_id = (
+                    SELECT id
+                    FROM roles
+                    WHERE name = $2
+                )
+            "#,
+            user_id,
+            role
+        )
+            .execute(db!())
+            .await?;
+
+        USER_ROLES_CACHE.invalidate(&user_id);
+
+        Ok(())
+    }
+
+    async fn exists(role: &str) -> RoleResult<bool> {
+        Ok(
+            query!(
+                r#"
+                    SELECT EXISTS (
+                        SELECT 1
+                        FROM roles
+                        WHERE name = $1
+                    )
+                "#,
+                role
+            )
+                .fetch_one(db!())
+                .await?
+                .exists
+                .unwrap_or(false)
+        )
+    }
+}
@@ -0,0 +1,312 @@
+use std::{ops::Add, time::Duration as StdDuration};
+use jsonwebtoken::errors::Error as JwtError;
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use sqlx::{query, Error as SqlxError};
+use thiserror::Error;
+use time::{Duration, OffsetDateTime};
+use crate::{db, helpers::{database::{cache::Cache, connection::DbConnectionError}, http::jwt::{decode_jwt, encode_jwt, Claims}, tokens::hash_token, totp::{generate_recovery_code, generate_secret, provisioning_uri, verify_code}}};
+
+pub const RECOVERY_CODES: usize = 10;
+
+// how long the user has to enter the code after the first step of the login.
+pub const CHALLENGE_LIFETIME: Duration = Duration::minutes(5);
+
+lazy_static! {
+    static ref ENABLED_CACHE: Cache<i32, bool> = Cache::new(StdDuration::from_secs(5));
+}
+
+#[derive(Error, Debug)]
+pub enum TwoFactorError {
+    #[error("{0:#}")]
+    DbQuery(#[from] SqlxError),
+
+    #[error("{0:#}")]
+    DbConn(#[from] DbConnectionError),
+
+    #[error("{0:#}")]
+    Jwt(#[from] JwtError),
+
+    #[error("Two-factor authentication is already enabled.")]
+    AlreadyEnabled,
+
+    #[error("Two-factor authentication is not enabled.")]
+    NotEnabled,
+
+    #[error("The two-factor code is not valid.")]
+    InvalidCode
+}
+
+type TwoFactorResult<R> = Result<R, TwoFactorError>;
+
+// what the user needs to add the account to an authenticator app.
+pub struct Enrollment {
+    pub secret: String,
+    pub uri: String
+}
+
+// the user that passed the first step of the login, signed into
+// the challenge cookie until the code is entered.
+#[derive(Serialize, Deserialize)]
+struct ChallengeClaims {
+    challenged_user: i32
+}
+
+// totp for a user, it's pending until the user proves the app
+// was set up by entering a code.
+pub struct TwoFactor;
+
+impl TwoFactor {
+    // starting again replaces the secret of a pending enrollment.
+    pub async fn enroll(user_id: i32, account: &str) -> TwoFactorResult<Enrollment> {
+        let secret = generate_secret();
+
+        let enrolled = query!(
+            r#"
+                INSERT INTO two_factor (user_id, secret)
+                VALUES ($1, $2)
+                ON CONFLICT (user_id) DO UPDATE
+                SET secret = EXCLUDED.secret, last_used_step = NULL, created_at = NOW()
+                WHERE two_factor.enabled_at IS NULL
+            "#,
+            user_id,
+            secret
+        )
+            .execute(db!())
+            .await?
+            .rows_affected();
+
+        if enrolled == 0 {
+            return Err(TwoFactorError::AlreadyEnabled);
+        }
+
+        Ok(Enrollment {
+            uri: provisioning_uri(&secret, account),
+            secret
+        })
+    }
+
+    // enables the enrollment, returns the recovery codes, which are only shown once.
+    pub async fn confirm(user_id: i32, code: &str) -> TwoFactorResult<Vec<String>> {
+        let pending = query!(
+            r#"
+                SELECT secret, enabled_at
+                FROM two_factor
+                WHERE user_id = $1
+            "#,
+            user_id
+        )
+            .fetch_optional(db!())
+            .await?
+            .ok_or(TwoFactorError::NotEnabled)?;
+
+        if pending.enabled_at.is_some() {
+            return Err(TwoFactorError::AlreadyEnabled);
+        }
+
+        let step = verify_code(&pending.secret, code, None)
+            .ok_or(TwoFactorError::InvalidCode)?;
+
+        query!(
+            r#"
+                UPDATE two_factor
+                SET enabled_at = NOW(), last_used_step = $2
+                WHERE user_id = $1
+            "#,
+            user_id,
+            step
+        )
+            .execute(db!())
+            .await?;
+
+        ENABLED_CACHE.invalidate(&user_id);
+
+        Self::regenerate_recovery_codes(user_id)
+            .await
+    }
+
+    // the previous codes stop working.
+    pub async fn regenerate_recovery_codes(user_id: i32) -> TwoFactorResult<Vec<String>> {
+        let codes = (0..RECOVERY_CODES)
+            .map(|_| generate_recovery_code())
+            .collect::<Vec<_>>();
+
+        let mut tx = db!()
+            .begin()
+            .await?;
+
+        query!(
+            r#"
+                DELETE FROM recovery_codes
+                WHERE user_id = $1
+            "#,
+            user_id
+        )
+            .execute(&mut *tx)
+            .await?;
+
+        query!(
+            r#"
+                INSERT INTO recovery_codes (code_hash, user_id)
+                SELECT UNNEST($1::TEXT[]), $2
+            "#,
+            &codes.iter()
+                .map(|code| hash_token(code))
+                .collect::<Vec<_>>(),
+            user_id
+        )
+            .execute(&mut *tx)
+            .await?;
+
+        tx.commit()
+            .await?;
+
+        Ok(codes)
+    }
+
+    // accepts a code of the app or an unused recovery code, which is consumed.
+    pub async fn verify(user_id: i32, code: &str) -> TwoFactorResult<()> {
+        let enabled = query!(
+            r#"
+                SELECT secret, last_used_step
+                FROM two_factor
+                WHERE user_id = $1
+                AND enabled_at IS NOT NULL
+            "#,
+            user_id
+        )
+            .fetch_optional(db!())
+            .await?
+            .ok_or(TwoFactorError::NotEnabled)?;
+
+        if let Some(step) = verify_code(&enabled.secret, code, enabled.last_used_step) {
+            // the same code can't be used twice even by concurrent requests.
+            let used = query!(
+                r#"
+                    UPDATE two_factor
+                    SET last_used_step = $2
+                    WHERE user_id = $1
+                    AND (last_used_step IS NULL OR last_used_step < $2)
+                "#,
+                user_id,
+                step
+            )
+                .execute(db!())
+                .await?
+                .rows_affected();
+
+            return match used {
+                0 => Err(TwoFactorError::InvalidCode),
+                _ => Ok(())
+            };
+        }
+
+        let consumed = query!(
+            r#"
+                UPDATE recovery_codes
+                SET used_at = NOW()
+                WHERE code_hash = $1
+                AND user_id = $2
+                AND used_at IS NULL
+            "#,
+            hash_token(&code.trim().to_lowercase()),
+            user_id
+        )
+            .execute(db!())
+            .await?
+            .rows_affected();
+
+        match consumed {
+            0 => Err(TwoFactorError::InvalidCode),
+            _ => Ok(())
+        }
+    }
+
+    pub async fn disable(user_id: i32) -> TwoFactorResult<()> {
+        query!(
+            r#"
+                DELETE FROM two_factor
+                WHERE user_id = $1
+            "#,
+            user_id
+        )
+            .execute(db!())
+            .await?;
+
+        query!(
+            r#"
+                DELETE FROM recovery_codes
+                WHERE user_id = $1
+            "#,
+            user_id
+        )
+            .execute(db!())
+            .await?;
+
+        ENABLED_CACHE.invalidate(&user_id);
+
+        Ok(())
+    }
+
+    pub fn challenge(user_id: i32) -> TwoFactorResult<String> {
+        Ok(encode_jwt(&Claims::new(
+            OffsetDateTime::now_utc()
+                .add(CHALLENGE_LIFETIME)
+                .unix_timestamp() as usize,
+            ChallengeClaims {
+                challenged_user: user_id
+            }
+        ))?)
+    }
+
+    // the user of a challenge that didn't expire.
+    pub fn challenged_user(challenge: &str) -> Option<i32> {
+        decode_jwt::<Claims<ChallengeClaims>>(challenge)
+            .ok()
+            .map(|claims| claims.into_inner().challenged_user)
+    }
+
+    pub async fn is_enabled(user_id: i32) -> TwoFactorResult<bool> {
+        if let Some(enabled) = ENABLED_CACHE.get(&user_id) {
+            return Ok(enabled);
+        }
+
+        let enabled = query!(
+            r#"
+                SELECT EXISTS (
+                    SELECT 1
+                    FROM two_factor
+                    WHERE user_id = $1
+                    AND enabled_at IS NOT NULL
+                )
+            "#,
+            user_id
+        )
+            .fetch_one(db!())
+            .await?
+            .exists
+            .unwrap_or(false);
+
+        ENABLED_CACHE.insert(user_id, enabled);
+
+        Ok(enabled)
+    }
+
+    pub async fn recovery_codes_left(user_id: i32) -> TwoFactorResult<i64> {
+        Ok(
+            query!(
+                r#"
+                    SELECT COUNT(*)
+                    FROM recovery_codes
+                    WHERE user_id = $1
+                    AND used_at IS NULL
+                "#,
+                user_id
+            )
+                .fetch_one(db!())
+                .await?
+                .count
+                .unwrap_or(0)
+        )
+    }
+}
@@ -1,13 +1,17 @@
-use std::{future::{ready, Ready}, num::ParseIntError, ops::Add, time::{SystemTime, SystemTimeError, UNIX_EPOCH}};
-use actix_web::{cookie::time::Duration, dev::Payload, error::ErrorUnauthorized, Error as ActixError, FromRequest, HttpRequest};
+use std::{ops::Add, time::{Duration as StdDuration, SystemTime, SystemTimeError, UNIX_EPOCH}};
+use actix_web::{cookie::time::Duration, dev::Payload, FromRequest, HttpRequest};
 use bcrypt::{hash, verify, BcryptError, DEFAULT_COST};
-use jsonwebtoken::{decode, encode, errors::Error as JwtError, Header, Validation};
+use futures_util::future::LocalBoxFuture;
+use jsonwebtoken::errors::Error as JwtError;
+use lazy_static::lazy_static;
+use log::error;
 use serde::{Deserialize, Serialize};
-use sqlx::{prelude::FromRow, query, query_as, Error as SqlxError};
+use sqlx::{prelude::FromRow, query, query_as, Error as SqlxError, PgConnection, PgExecutor};
 use time::OffsetDateTime;
 use thiserror::Error;
-use crate::{db, helpers::{database::connection::DbConnectionError, http::jwt::Claims}, jwt_hash};
-use base64::{engine::general_purpose::URL_SAFE, DecodeError, Engine as _};
+use uuid::Uuid;
+use crate::{db, helpers::{cells::{color::Color, position::Position, processes::anonymize_author}, database::{cache::Cache, connection::DbConnectionError}, http::{api_error::ApiError, cookies::ACCESS_COOKIE, jwt::{decode_jwt, encode_jwt, Claims}}, mail::{mailer::Mail, queue::enqueue, templates::Template}, validation::{canonical_username, normalize_email}}};
+use super::{api_token::{ApiScope, ApiToken, ApiTokenError, TOKEN_PREFIX}, oauth_identity::{IdentityError, OAuthIdentity}, role::{Role, RoleError, DEFAULT_ROLE}, user_session::{SessionError, SessionOrigin, UserSession, ACCESS_TOKEN_LIFETIME}, user_token::{TokenError, TokenPurpose, UserToken}, username_denylist::DenylistError};
 
 #[derive(Error, Debug)]
 pub enum UserError {
@@ -29,27 +33,93 @@ pub enum UserError {
     #[error("Cannot consume a token at this time.")]
     Unconsumable,
 
-    #[error("{0:#}")]
-    Base64(#[from] DecodeError),
-
     #[error("The specified activation token is not valid.")]
     InvalidActivationToken,
 
+    #[error("The account is already activated.")]
+    AlreadyActivated,
+
+    #[error("The specified password reset token is not valid.")]
+    InvalidPasswordResetToken,
+
+    #[error("The specified email change token is not valid.")]
+    InvalidEmailChangeToken,
+
+    #[error("This email is already in use.")]
+    EmailTaken,
+
+    #[error("This username is already in use.")]
+    UsernameTaken,
+
+    #[error("The username can be changed again on {}.", .0.date())]
+    UsernameCooldown(OffsetDateTime),
+
+    #[error("The session is not valid.")]
+    InvalidSession,
+
     #[error("{0:#}")]
-    ParseInt(#[from] ParseIntError)
+    Session(#[from] SessionError),
+
+    #[error("{0:#}")]
+    Token(#[from] TokenError),
+
+    #[error("{0:#}")]
+    Role(#[from] RoleError),
+
+    #[error("{0:#}")]
+    ApiToken(#[from] ApiTokenError),
+
+    #[error("{0:#}")]
+    Identity(#[from] IdentityError),
+
+    #[error("{0:#}")]
+    Denylist(#[from] DenylistError)
+}
+
+// how long a user has to wait between username changes.
+pub const USERNAME_CHANGE_COOLDOWN: Duration = Duration::days(30);
+
+pub type UserResult<R> = Result<R, UserError>;
+
+lazy_static! {
+    static ref USER_CACHE: Cache<i32, User> = Cache::new(StdDuration::from_secs(5));
 }
 
-type UserResult<R> = Result<R, UserError>;
+// the session identifies the user, the user itself is always read from the database.
+// the roles are only informative, they may be as old as the token, so guards read the current ones.
+#[derive(Serialize, Deserialize)]
+pub struct SessionClaims {
+    sub: i32,
+    sid: Uuid,
+    jti: Uuid,
 
-#[derive(FromRow, Serialize, Deserialize, Clone)]
+    #[serde(default)]
+    roles: Vec<String>
+}
+
+// lets browsers authenticate sockets, they can't read the access cookie.
+// it's only valid for a moment so it's not worth stealing.
+#[derive(Serialize, Deserialize)]
+struct SocketTicketClaims {
+    ticket_user: i32,
+    ticket_session: Uuid
+}
+
+const SOCKET_TICKET_LIFETIME: Duration = Duration::minutes(1);
+
+// never sent to clients as is, see the profile views.
+#[derive(FromRow, Clone)]
 pub struct User {
     id: i32,
     email: String,
     username: String,
-    password: String,
+    // users that signed up with an external provider have no password.
+    password: Option<String>,
     credits: i32,
     next_free_credit: OffsetDateTime,
-    activated: bool
+    activated: bool,
+    created_at: OffsetDateTime,
+    pixels_placed: i32
 }
 
 #[derive(Clone)]
@@ -58,102 +128,258 @@ pub enum MaybeUser {
     Unauthorized
 }
 
+// the user behind a valid access token, along with the session it belongs to.
+// api tokens are sessions too, identified by the token id, that never expire and
+// can only be used for their scopes.
+#[derive(Clone)]
+pub struct SessionUser {
+    user: User,
+    session_id: Uuid,
+    expires_at: Option<OffsetDateTime>,
+    scopes: Option<Vec<ApiScope>>
+}
+
 impl User {
-    pub async fn exists(email: &String, username: &String) -> UserResult<bool> {
-        Ok(query!(
+    pub async fn insert(email: String, username: String, password: String) -> UserResult<Self> {
+        let user = query_as!(
+            Self,
             r#"
-                SELECT EXISTS (
-                    SELECT 1
-                    FROM users
-                    WHERE email = $1 OR username = $2
-                )
+                INSERT INTO users (email, username, canonical_username, password)
+                VALUES ($1, $2, $3, $4)
+                RETURNING id, email, username, password, credits, next_free_credit, activated, created_at, pixels_placed
             "#,
-            email,
-            username
+            normalize_email(&email),
+            username,
+            canonical_username(&username),
+            hash(&password, DEFAULT_COST)?
         )
             .fetch_one(db!())
-            .await?
-            .exists
-            .unwrap_or(false))
+            .await
+            .map_err(insert_error)?;
+
+        Role::grant(user.id, DEFAULT_ROLE)
+            .await?;
+
+        Ok(user)
     }
 
-    pub async fn insert(email: String, username: String, password: String) -> UserResult<Self> {
-        query_as!(
+    // creates a user signed up with an external provider and links its identity,
+    // the provider already confirmed the email when it's verified.
+    pub async fn insert_external(email: String, username: String, activated: bool, provider: &str, subject: &str) -> UserResult<Self> {
+        let user = query_as!(
             Self,
             r#"
-                INSERT INTO users (email, username, password)
-                VALUES ($1, $2, $3)
-                RETURNING *
+                INSERT INTO users (email, username, canonical_username, activated)
+                VALUES ($1, $2, $3, $4)
+                RETURNING id, email, username, password, credits, next_free_credit, activated, created_at, pixels_placed
             "#,
-            email,
+            normalize_email(&email),
             username,
-            hash(&password, DEFAULT_COST)?
+            canonical_username(&username),
+            activated
         )
             .fetch_one(db!())
             .await
-            .map_err(|err| UserError::DbQuery(err))
+            .map_err(insert_error)?;
+
+        Role::grant(user.id, DEFAULT_ROLE)
+            .await?;
+
+        OAuthIdentity::link(user.id, provider, subject)
+            .await?;
+
+        Ok(user)
     }
 
     pub async fn login(email: String, password: String) -> UserResult<Option<Self>> {
         query_as!(
             Self,
             r#"
-                SELECT *
+                SELECT id, email, username, password, credits, next_free_credit, activated, created_at, pixels_placed
                 FROM users
-                WHERE email = $1
+                WHERE LOWER(email) = $1
             "#,
-            email
+            normalize_email(&email)
         )
             .fetch_optional(db!())
             .await
             .map(|mut user| user
-                .take_if(|user| verify(
-                    password,
-                    &user.password
-                )
-                    .unwrap_or(false))
+                .take_if(|user| user.verify_password(&password))
             )
-            .map_err(|err| UserError::DbQuery(err))
-    }
-
-    pub fn from_jwt(token: String) -> Result<Self, UserError> {
-        Ok(
-            decode::<Claims<User>>(
-                &token,
-                jwt_hash!(decode),
-                &Validation::default()
-            )?
-                .claims
-                .into_inner()
+            .map_err(UserError::DbQuery)
+    }
+
+    // the user may be up to a few seconds old, writes go through
+    // queries that check the current row instead.
+    pub async fn find(id: i32) -> UserResult<Option<Self>> {
+        if let Some(user) = USER_CACHE.get(&id) {
+            return Ok(Some(user));
+        }
+
+        let user = query_as!(
+            Self,
+            r#"
+                SELECT id, email, username, password, credits, next_free_credit, activated, created_at, pixels_placed
+                FROM users
+                WHERE id = $1
+            "#,
+            id
+        )
+            .fetch_optional(db!())
+            .await?;
+
+        if let Some(user) = &user {
+            USER_CACHE.insert(id, user.clone());
+        }
+
+        Ok(user)
+    }
+
+    // lookalikes of the username find the user too, an exact match goes first
+    // for the users from before the canonical usernames.
+    pub async fn find_by_username(username: &String) -> UserResult<Option<Self>> {
+        Ok(query_as!(
+            Self,
+            r#"
+                SELECT id, email, username, password, credits, next_free_credit, activated, created_at, pixels_placed
+                FROM users
+                WHERE username = $1
+                OR canonical_username = $2
+                ORDER BY username = $1 DESC
+                LIMIT 1
+            "#,
+            username,
+            canonical_username(username)
+        )
+            .fetch_optional(db!())
+            .await?)
+    }
+
+    pub async fn find_by_email(email: &str) -> UserResult<Option<Self>> {
+        Ok(query_as!(
+            Self,
+            r#"
+                SELECT id, email, username, password, credits, next_free_credit, activated, created_at, pixels_placed
+                FROM users
+                WHERE LOWER(email) = $1
+            "#,
+            normalize_email(email)
         )
+            .fetch_optional(db!())
+            .await?)
     }
 
-    pub fn jwt(&self) -> Result<String, UserError> {
+    // users from before the canonical usernames get theirs when the server starts, in place
+    // of the placeholder the migration gave them. the ones that look like an earlier user
+    // get one of their own so both can be found.
+    pub async fn canonicalize_usernames() -> UserResult<()> {
+        let pending = query!(
+            r#"
+                SELECT id, username
+                FROM users
+                WHERE canonical_username LIKE '#%'
+                ORDER BY id
+            "#
+        )
+            .fetch_all(db!())
+            .await?;
+
+        for user in pending {
+            let canonical = canonical_username(&user.username);
+
+            for canonical in [canonical.clone(), format!("{canonical}#{}", user.id)] {
+                let updated = query!(
+                    r#"
+                        UPDATE users
+                        SET canonical_username = $1
+                        WHERE id = $2
+                    "#,
+                    canonical,
+                    user.id
+                )
+                    .execute(db!())
+                    .await;
+
+                match updated {
+                    Ok(_) => break,
+                    Err(err) if err.as_database_error().is_some_and(|db_err| db_err.is_unique_violation()) => {},
+                    Err(err) => return Err(err.into())
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    // opens a new session for the user, returns its access and refresh tokens.
+    pub async fn start_session(&self, origin: SessionOrigin) -> UserResult<(String, String)> {
+        let (session, refresh_token) = UserSession::create(self.id, origin)
+            .await?;
+
+        Ok((self.jwt(session.id()).await?, refresh_token))
+    }
+
+    pub async fn jwt(&self, session_id: Uuid) -> Result<String, UserError> {
         let exp = SystemTime::now()
-            .add(Duration::hours(12))
+            .add(ACCESS_TOKEN_LIFETIME)
             .duration_since(UNIX_EPOCH)?
             .as_secs();
 
-        Ok(encode(
-            &Header::default(),
-            &Claims::new(exp as usize, self),
-            jwt_hash!(encode)
-        )?)
+        Ok(encode_jwt(&Claims::new(
+            exp as usize,
+            SessionClaims {
+                sub: self.id,
+                sid: session_id,
+                jti: Uuid::new_v4(),
+                roles: Role::of_user(self.id).await?
+            }
+        ))?)
+    }
+
+    pub fn socket_ticket(&self, session_id: Uuid) -> UserResult<String> {
+        Ok(encode_jwt(&Claims::new(
+            OffsetDateTime::now_utc()
+                .add(SOCKET_TICKET_LIFETIME)
+                .unix_timestamp() as usize,
+            SocketTicketClaims {
+                ticket_user: self.id,
+                ticket_session: session_id
+            }
+        ))?)
     }
 
     pub fn name(&self) -> &String {
         &self.username
     }
 
-    pub fn activation_token(&self) -> String {
-        URL_SAFE.encode(format!("{};{}", self.id, self.email))
+    // issues a new activation token, the previous ones stop working.
+    async fn activation_token(&self) -> UserResult<String> {
+        if self.activated {
+            return Err(UserError::AlreadyActivated);
+        }
+
+        Ok(UserToken::issue(self.id, TokenPurpose::Activation).await?)
+    }
+
+    // the mail is delivered in the background, failing to send it doesn't fail the request.
+    pub async fn send_activation(&self) -> UserResult<()> {
+        let token = self.activation_token()
+            .await?;
+
+        enqueue(Mail::new(
+            &self.email,
+            Template::Activation {
+                username: &self.username,
+                token: &token
+            }
+        ));
+
+        Ok(())
     }
 
-    pub async fn activate(token: &String) -> UserResult<()> {
-        let decoded = URL_SAFE.decode(token)?;
-        let decoded = String::from_utf8_lossy(&decoded);
-        let (id, email) = decoded
-            .split_once(";")
+    pub async fn activate(token: &str) -> UserResult<()> {
+        let id = UserToken::consume(token, TokenPurpose::Activation)
+            .await?
             .ok_or(UserError::InvalidActivationToken)?;
 
         query!(
@@ -161,57 +387,389 @@ impl User {
                 UPDATE users
                 SET activated = true
                 WHERE id = $1
-                AND email = $2
             "#,
-            id.parse::<i32>()?,
-            email
+            id
         )
             .execute(db!())
             .await?;
 
+        USER_CACHE.invalidate(&id);
+
         Ok(())
     }
 
-    pub async fn consume_credit(&mut self) -> UserResult<()> {
-        let now = OffsetDateTime::now_utc();
+    pub async fn send_password_reset(&self) -> UserResult<()> {
+        let token = UserToken::issue(self.id, TokenPurpose::PasswordReset)
+            .await?;
 
-        if self.next_free_credit > now {
-            if self.credits <= 0 {
-                return Err(UserError::Unconsumable);
+        enqueue(Mail::new(
+            &self.email,
+            Template::PasswordReset {
+                username: &self.username,
+                token: &token
             }
+        ));
 
-            query!(
-                r#"
-                    UPDATE users
-                    SET credits = credits - 1
-                    WHERE id = $1
-                "#,
-                self.id
-            )
-                .execute(db!())
-                .await?;
+        Ok(())
+    }
+
+    // changes the password and logs the user out everywhere,
+    // returns the sessions and api tokens that were revoked.
+    pub async fn reset_password(token: &str, password: String) -> UserResult<Vec<Uuid>> {
+        let id = UserToken::consume(token, TokenPurpose::PasswordReset)
+            .await?
+            .ok_or(UserError::InvalidPasswordResetToken)?;
+
+        let mut tx = db!()
+            .begin()
+            .await?;
+
+        Self::set_password(&mut *tx, id, &password)
+            .await?;
+
+        let revoked = Self::revoke_access(&mut tx, id, None)
+            .await?;
+
+        tx.commit()
+            .await?;
+
+        Ok(revoked)
+    }
+
+    pub fn verify_password(&self, password: &str) -> bool {
+        self.password
+            .as_ref()
+            .is_some_and(|hash| verify(password, hash).unwrap_or(false))
+    }
+
+    pub fn has_password(&self) -> bool {
+        self.password.is_some()
+    }
+
+    // the session the change is made from stays logged in, returns
+    // the rest of sessions and the api tokens, which were revoked.
+    pub async fn change_password(&self, password: &str, current_session: Uuid) -> UserResult<Vec<Uuid>> {
+        let mut tx = db!()
+            .begin()
+            .await?;
+
+        Self::set_password(&mut *tx, self.id, password)
+            .await?;
+
+        let revoked = Self::revoke_access(&mut tx, self.id, Some(current_session))
+            .await?;
+
+        tx.commit()
+            .await?;
+
+        Ok(revoked)
+    }
+
+    // logs the user out of every device and revokes its api tokens,
+    // returns the ids of both so their sockets can be closed.
+    pub async fn log_out_everywhere(&self) -> UserResult<Vec<Uuid>> {
+        let mut tx = db!()
+            .begin()
+            .await?;
+
+        let revoked = Self::revoke_access(&mut tx, self.id, None)
+            .await?;
+
+        tx.commit()
+            .await?;
+
+        Ok(revoked)
+    }
+
+    // an api token outliving a compromised password would keep the attacker in.
+    async fn revoke_access(tx: &mut PgConnection, id: i32, keep: Option<Uuid>) -> UserResult<Vec<Uuid>> {
+        let mut revoked = match keep {
+            Some(keep) => UserSession::revoke_others(&mut *tx, id, keep)
+                .await?,
+            None => UserSession::revoke_all(&mut *tx, id)
+                .await?
+        };
+
+        revoked.extend(
+            ApiToken::revoke_all(&mut *tx, id)
+                .await?
+        );
+
+        Ok(revoked)
+    }
+
+    async fn set_password(executor: impl PgExecutor<'_>, id: i32, password: &str) -> UserResult<()> {
+        query!(
+            r#"
+                UPDATE users
+                SET password = $1
+                WHERE id = $2
+            "#,
+            hash(password, DEFAULT_COST)?,
+            id
+        )
+            .execute(executor)
+            .await?;
 
-            self.credits -= 1;
+        Ok(())
+    }
+
+    // the email doesn't change until the new address is confirmed.
+    pub async fn request_email_change(&self, email: &str) -> UserResult<()> {
+        let email = &normalize_email(email);
 
-            return Ok(());
+        if Self::find_by_email(email).await?.is_some() {
+            return Err(UserError::EmailTaken);
         }
 
-        let next_time = now
-            .add(Duration::hours(12));
+        let token = UserToken::issue_with_payload(self.id, TokenPurpose::EmailChange, Some(email))
+            .await?;
+
+        enqueue(Mail::new(
+            email,
+            Template::EmailChange {
+                username: &self.username,
+                token: &token
+            }
+        ));
+
+        Ok(())
+    }
+
+    pub async fn confirm_email_change(token: &str) -> UserResult<()> {
+        let (id, Some(email)) = UserToken::consume_with_payload(token, TokenPurpose::EmailChange)
+            .await?
+            .ok_or(UserError::InvalidEmailChangeToken)?
+        else {
+            return Err(UserError::InvalidEmailChangeToken);
+        };
+
+        query!(
+            r#"
+                UPDATE users
+                SET email = $1
+                WHERE id = $2
+            "#,
+            normalize_email(&email),
+            id
+        )
+            .execute(db!())
+            .await
+            .map_err(|err| match err.as_database_error() {
+                Some(db_err) if db_err.is_unique_violation() => UserError::EmailTaken,
+                _ => UserError::DbQuery(err)
+            })?;
+
+        USER_CACHE.invalidate(&id);
+
+        Ok(())
+    }
+
+    // the previous usernames are kept for the moderators.
+    pub async fn change_username(&self, username: &str) -> UserResult<()> {
+        let last_change = query!(
+            r#"
+                SELECT MAX(created_at) AS last
+                FROM username_changes
+                WHERE user_id = $1
+            "#,
+            self.id
+        )
+            .fetch_one(db!())
+            .await?
+            .last;
+
+        if let Some(available_at) = last_change.map(|last| last + USERNAME_CHANGE_COOLDOWN) {
+            if available_at > OffsetDateTime::now_utc() {
+                return Err(UserError::UsernameCooldown(available_at));
+            }
+        }
+
+        let mut tx = db!()
+            .begin()
+            .await?;
+
+        query!(
+            r#"
+                UPDATE users
+                SET username = $1, canonical_username = $2
+                WHERE id = $3
+            "#,
+            username,
+            canonical_username(username),
+            self.id
+        )
+            .execute(&mut *tx)
+            .await
+            .map_err(|err| match err.as_database_error() {
+                Some(db_err) if db_err.is_unique_violation() => UserError::UsernameTaken,
+                _ => UserError::DbQuery(err)
+            })?;
 
         query!(
+            r#"
+                INSERT INTO username_changes (user_id, old_username, new_username)
+                VALUES ($1, $2, $3)
+            "#,
+            self.id,
+            self.username,
+            username
+        )
+            .execute(&mut *tx)
+            .await?;
+
+        tx.commit()
+            .await?;
+
+        USER_CACHE.invalidate(&self.id);
+
+        Ok(())
+    }
+
+    // the free credit is consumed first, the conditions are checked by the
+    // updates themselves so concurrent sessions can't consume the same credit.
+    pub async fn consume_credit(&mut self) -> UserResult<()> {
+        let next_time = OffsetDateTime::now_utc()
+            .add(Duration::hours(12));
+
+        let free = query!(
             r#"
                 UPDATE users
                 SET next_free_credit = $1
                 WHERE id = $2
+                AND next_free_credit <= NOW()
+                RETURNING next_free_credit
             "#,
             next_time,
             self.id
+        )
+            .fetch_optional(db!())
+            .await?;
+
+        USER_CACHE.invalidate(&self.id);
+
+        if let Some(free) = free {
+            self.next_free_credit = free.next_free_credit;
+            return self.record_credit_usage(true).await;
+        }
+
+        let paid = query!(
+            r#"
+                UPDATE users
+                SET credits = credits - 1
+                WHERE id = $1
+                AND credits > 0
+                RETURNING credits
+            "#,
+            self.id
+        )
+            .fetch_optional(db!())
+            .await?
+            .ok_or(UserError::Unconsumable)?;
+
+        self.credits = paid.credits;
+
+        self.record_credit_usage(false)
+            .await
+    }
+
+    // kept so the user can export its credit history.
+    async fn record_credit_usage(&self, free: bool) -> UserResult<()> {
+        query!(
+            r#"
+                INSERT INTO credit_usages (user_id, free)
+                VALUES ($1, $2)
+            "#,
+            self.id,
+            free
         )
             .execute(db!())
             .await?;
 
-        self.next_free_credit = next_time;
+        Ok(())
+    }
+
+    // the cells keep their colors but stop pointing at the user, the rest of its data
+    // is deleted along the row, returns the sessions and api tokens that were revoked.
+    pub async fn delete(&self) -> UserResult<Vec<Uuid>> {
+        let mut tx = db!()
+            .begin()
+            .await?;
+
+        let revoked = Self::revoke_access(&mut tx, self.id, None)
+            .await?;
+
+        query!(
+            r#"
+                DELETE FROM login_attempts
+                WHERE email = $1
+            "#,
+            self.email.to_lowercase()
+        )
+            .execute(&mut *tx)
+            .await?;
+
+        query!(
+            r#"
+                DELETE FROM users
+                WHERE id = $1
+            "#,
+            self.id
+        )
+            .execute(&mut *tx)
+            .await?;
+
+        tx.commit()
+            .await?;
+
+        USER_CACHE.invalidate(&self.id);
+
+        // the cells are only anonymized once the account is surely gone,
+        // the account can't be brought back if this fails, so it's only logged.
+        if let Err(err) = anonymize_author(self.id).await {
+            error!("Couldn't anonymize the cells of the deleted user {}: {err}", self.id);
+        }
+
+        Ok(revoked)
+    }
+
+    // counts the pixel and keeps it in the history of the user,
+    // the canvas only has the last author of every cell.
+    pub async fn record_pixel(&mut self, position: Position, color: Color) -> UserResult<()> {
+        let mut tx = db!()
+            .begin()
+            .await?;
+
+        query!(
+            r#"
+                INSERT INTO painted_pixels (user_id, x, y, color)
+                VALUES ($1, $2, $3, $4)
+            "#,
+            self.id,
+            position.x() as i32,
+            position.y() as i32,
+            i32::from(color)
+        )
+            .execute(&mut *tx)
+            .await?;
+
+        self.pixels_placed = query!(
+            r#"
+                UPDATE users
+                SET pixels_placed = pixels_placed + 1
+                WHERE id = $1
+                RETURNING pixels_placed
+            "#,
+            self.id
+        )
+            .fetch_one(&mut *tx)
+            .await?
+            .pixels_placed;
+
+        tx.commit()
+            .await?;
+
+        USER_CACHE.invalidate(&self.id);
 
         Ok(())
     }
@@ -220,73 +778,213 @@ impl User {
         self.next_free_credit <= OffsetDateTime::now_utc() || self.credits > 0
     }
 
-    #[allow(unused)]
     pub fn activated(&self) -> bool {
         self.activated
     }
 
-    #[allow(unused)]
     pub fn credits(&self) -> i32 {
         self.credits
     }
 
-    #[allow(unused)]
+    pub fn next_free_credit(&self) -> OffsetDateTime {
+        self.next_free_credit
+    }
+
     pub fn username(&self) -> &str {
         &self.username
     }
 
-    #[allow(unused)]
     pub fn email(&self) -> &str {
         &self.email
     }
 
-    #[allow(unused)]
+    pub fn created_at(&self) -> OffsetDateTime {
+        self.created_at
+    }
+
+    pub fn pixels_placed(&self) -> i32 {
+        self.pixels_placed
+    }
+
     pub fn id(&self) -> i32 {
         self.id
     }
 }
 
-impl TryFrom<&HttpRequest> for User {
-    type Error = ActixError;
+impl SessionUser {
+    pub async fn from_jwt(token: String) -> UserResult<Self> {
+        let claims = decode_jwt::<Claims<SessionClaims>>(&token)?;
 
-    fn try_from(value: &HttpRequest) -> Result<Self, Self::Error> {
-        value.cookie("Session")
-            .and_then(|cookie| Self::from_jwt(
-                    cookie
-                        .value()
-                        .to_string()
-                )
-                    .ok()
-            )
-            .map_or(
-                Err(ErrorUnauthorized("Provide Session cookie for this endpoint.")),
-                |res| Ok(res)
-            )
+        let expires_at = claims.expires_at();
+        let SessionClaims { sub, sid, .. } = claims.into_inner();
+
+        if !UserSession::is_active(sid).await? {
+            return Err(UserError::InvalidSession);
+        }
+
+        let user = User::find(sub)
+            .await?
+            .ok_or(UserError::InvalidSession)?;
+
+        Ok(Self {
+            user,
+            session_id: sid,
+            expires_at: Some(expires_at),
+            scopes: None
+        })
+    }
+
+    // the socket keeps the session until the access token it was refreshed with expires.
+    pub async fn from_socket_ticket(ticket: &str) -> UserResult<Self> {
+        let SocketTicketClaims { ticket_user, ticket_session } = decode_jwt::<Claims<SocketTicketClaims>>(ticket)?
+            .into_inner();
+
+        let expires_at = UserSession::access_expires_at(ticket_session)
+            .await?
+            .ok_or(UserError::InvalidSession)?;
+
+        let user = User::find(ticket_user)
+            .await?
+            .ok_or(UserError::InvalidSession)?;
+
+        Ok(Self {
+            user,
+            session_id: ticket_session,
+            expires_at: Some(expires_at),
+            scopes: None
+        })
+    }
+
+    pub async fn from_api_token(token: String) -> UserResult<Self> {
+        let token = ApiToken::authenticate(&token)
+            .await?
+            .ok_or(UserError::InvalidSession)?;
+
+        let user = User::find(token.user_id())
+            .await?
+            .ok_or(UserError::InvalidSession)?;
+
+        Ok(Self {
+            user,
+            session_id: token.id(),
+            expires_at: None,
+            scopes: Some(token.scopes())
+        })
+    }
+
+    // for the places that take both access and api tokens.
+    pub async fn from_token(token: String) -> UserResult<Self> {
+        if token.starts_with(TOKEN_PREFIX) {
+            Self::from_api_token(token)
+                .await
+        } else {
+            Self::from_jwt(token)
+                .await
+        }
+    }
+
+    pub fn user(&self) -> &User {
+        &self.user
+    }
+
+    pub fn into_user(self) -> User {
+        self.user
+    }
 
+    pub fn session_id(&self) -> Uuid {
+        self.session_id
+    }
+
+    pub fn expires_at(&self) -> Option<OffsetDateTime> {
+        self.expires_at
+    }
+
+    // none when the user logged in, everything is allowed then.
+    pub fn scopes(&self) -> Option<&[ApiScope]> {
+        self.scopes
+            .as_deref()
+    }
+
+    pub fn allows(&self, scope: ApiScope) -> bool {
+        self.scopes
+            .as_ref()
+            .is_none_or(|scopes| scopes.contains(&scope))
     }
 }
 
-impl FromRequest for User {
-    type Error = ActixError;
+// the checks before inserting a user can race with another signup.
+fn insert_error(err: SqlxError) -> UserError {
+    match err.as_database_error().and_then(|db_err| db_err.constraint()) {
+        Some("users_email_lower_key") => UserError::EmailTaken,
+        Some("users_username_key" | "users_canonical_username_key") => UserError::UsernameTaken,
+        _ => UserError::DbQuery(err)
+    }
+}
+
+// the token sent as `Authorization: Bearer <token>`.
+pub fn bearer_token(req: &HttpRequest) -> Option<String> {
+    req.headers()
+        .get("Authorization")?
+        .to_str()
+        .ok()?
+        .strip_prefix("Bearer ")
+        .map(|token| token.trim().to_string())
+}
 
-    type Future = Ready<Result<Self, Self::Error>>;
+// only reads the session cookie, routes that take api tokens use RequireScope.
+impl FromRequest for SessionUser {
+    type Error = ApiError;
+
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
 
     fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
-        ready(req.try_into())
+        let token = req.cookie(ACCESS_COOKIE)
+            .map(|cookie| cookie.value().to_string());
+
+        Box::pin(async move {
+            let token = token
+                .ok_or(ApiError::unauthorized("Provide Session cookie for this endpoint."))?;
+
+            Self::from_jwt(token)
+                .await
+                .map_err(|err| match err {
+                    UserError::Jwt(_) | UserError::InvalidSession
+                        => ApiError::unauthorized("The session is not valid."),
+                    err => err.into()
+                })
+        })
+    }
+}
+
+impl FromRequest for User {
+    type Error = ApiError;
+
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, payload: &mut Payload) -> Self::Future {
+        let session = SessionUser::from_request(req, payload);
+
+        Box::pin(async move {
+            session
+                .await
+                .map(SessionUser::into_user)
+        })
     }
 }
 
 impl FromRequest for MaybeUser {
-    type Error = ActixError;
+    type Error = ApiError;
 
-    type Future = Ready<Result<Self, Self::Error>>;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
 
-    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
-        ready(Ok(
-            match req.try_into() {
+    fn from_request(req: &HttpRequest, payload: &mut Payload) -> Self::Future {
+        let user = User::from_request(req, payload);
+
+        Box::pin(async move {
+            Ok(match user.await {
                 Ok(user) => Self::Authorized(user),
                 Err(_) => Self::Unauthorized
-            }
-        ))
+            })
+        })
     }
 }
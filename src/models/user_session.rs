@@ -0,0 +1,427 @@
+use std::{ops::Add, time::Duration as StdDuration};
+use actix_web::HttpRequest;
+use lazy_static::lazy_static;
+use serde::Serialize;
+use sqlx::{query, query_as, Error as SqlxError, PgExecutor};
+use thiserror::Error;
+use time::{Duration, OffsetDateTime};
+use uuid::Uuid;
+use crate::{db, helpers::{database::{cache::Cache, connection::DbConnectionError}, http::client_ip::client_ip, tokens::{generate_token, hash_token}}};
+
+pub const ACCESS_TOKEN_LIFETIME: Duration = Duration::minutes(15);
+pub const SESSION_LIFETIME: Duration = Duration::days(30);
+
+// two tabs refreshing at once send the same token, the second one finds it
+// used by the first. that's only a leak after this long or when the token
+// that replaced it was used too.
+const REFRESH_REUSE_GRACE: Duration = Duration::seconds(10);
+
+// user agents are whatever the client sends, only this much is kept.
+const MAX_USER_AGENT_LENGTH: usize = 512;
+
+lazy_static! {
+    static ref ACTIVE_CACHE: Cache<Uuid, bool> = Cache::new(StdDuration::from_secs(5));
+}
+
+#[derive(Error, Debug)]
+pub enum SessionError {
+    #[error("{0:#}")]
+    DbQuery(#[from] SqlxError),
+
+    #[error("{0:#}")]
+    DbConn(#[from] DbConnectionError),
+
+    #[error("The refresh token is not valid.")]
+    InvalidRefreshToken,
+
+    #[error("The refresh token was already used, the session was revoked.")]
+    RefreshTokenReused(Uuid)
+}
+
+type SessionResult<R> = Result<R, SessionError>;
+
+// where a session was opened from.
+pub struct SessionOrigin {
+    ip: String,
+    user_agent: Option<String>
+}
+
+impl From<&HttpRequest> for SessionOrigin {
+    fn from(req: &HttpRequest) -> Self {
+        Self {
+            ip: client_ip(req),
+            user_agent: req.headers()
+                .get("User-Agent")
+                .and_then(|agent| agent.to_str().ok())
+                .map(|agent| agent.chars().take(MAX_USER_AGENT_LENGTH).collect())
+        }
+    }
+}
+
+// what the user sees about the devices it's logged in.
+#[derive(Serialize)]
+pub struct SessionDetails {
+    id: Uuid,
+    ip: Option<String>,
+    user_agent: Option<String>,
+    #[serde(with = "time::serde::rfc3339")]
+    created_at: OffsetDateTime,
+    #[serde(with = "time::serde::rfc3339")]
+    last_seen_at: OffsetDateTime,
+    #[serde(with = "time::serde::rfc3339")]
+    expires_at: OffsetDateTime
+}
+
+impl SessionDetails {
+    pub fn id(&self) -> Uuid {
+        self.id
+    }
+}
+
+// a login, it lives as long as its refresh tokens keep being rotated
+// and the access tokens are only valid while it's not revoked.
+pub struct UserSession {
+    id: Uuid,
+    user_id: i32
+}
+
+impl UserSession {
+    pub async fn create(user_id: i32, origin: SessionOrigin) -> SessionResult<(Self, String)> {
+        let id = Uuid::new_v4();
+        let expires_at = OffsetDateTime::now_utc()
+            .add(SESSION_LIFETIME);
+
+        let mut tx = db!()
+            .begin()
+            .await?;
+
+        query!(
+            r#"
+                INSERT INTO sessions (id, user_id, expires_at, ip, user_agent)
+                VALUES ($1, $2, $3, $4, $5)
+            "#,
+            id,
+            user_id,
+            expires_at,
+            origin.ip,
+            origin.user_agent
+        )
+            .execute(&mut *tx)
+            .await?;
+
+        let refresh_token = generate_token();
+
+        query!(
+            r#"
+                INSERT INTO refresh_tokens (token_hash, session_id, expires_at)
+                VALUES ($1, $2, $3)
+            "#,
+            hash_token(&refresh_token),
+            id,
+            expires_at
+        )
+            .execute(&mut *tx)
+            .await?;
+
+        tx.commit()
+            .await?;
+
+        Ok((Self { id, user_id }, refresh_token))
+    }
+
+    // consumes the refresh token for a new one, a token that was already
+    // used means it leaked, so the whole session is revoked.
+    pub async fn refresh(refresh_token: &str) -> SessionResult<(Self, String)> {
+        let token_hash = hash_token(refresh_token);
+
+        let mut tx = db!()
+            .begin()
+            .await?;
+
+        let current = query!(
+            r#"
+                SELECT r.session_id, r.used_at, s.user_id, COALESCE(
+                    r.used_at > $2 AND NOT EXISTS (
+                        SELECT 1
+                        FROM refresh_tokens n
+                        WHERE n.session_id = r.session_id
+                        AND n.used_at > r.used_at
+                    ),
+                    FALSE
+                ) AS "in_grace!"
+                FROM refresh_tokens r
+                JOIN sessions s ON s.id = r.session_id
+                WHERE r.token_hash = $1
+                AND r.expires_at > NOW()
+                AND s.expires_at > NOW()
+                AND s.revoked_at IS NULL
+                FOR UPDATE OF r
+            "#,
+            token_hash,
+            OffsetDateTime::now_utc() - REFRESH_REUSE_GRACE
+        )
+            .fetch_optional(&mut *tx)
+            .await?
+            .ok_or(SessionError::InvalidRefreshToken)?;
+
+        if current.used_at.is_some() && !current.in_grace {
+            tx.rollback()
+                .await?;
+
+            Self::revoke(current.session_id)
+                .await?;
+
+            return Err(SessionError::RefreshTokenReused(current.session_id));
+        }
+
+        // a racing refresh gets a token of its own, the next regular one
+        // drops whichever of them the browser didn't keep.
+        if current.used_at.is_none() {
+            query!(
+                r#"
+                    UPDATE refresh_tokens
+                    SET expires_at = NOW()
+                    WHERE session_id = $1
+                    AND used_at IS NULL
+                    AND token_hash <> $2
+                "#,
+                current.session_id,
+                token_hash
+            )
+                .execute(&mut *tx)
+                .await?;
+        }
+
+        let expires_at = OffsetDateTime::now_utc()
+            .add(SESSION_LIFETIME);
+
+        query!(
+            r#"
+                UPDATE refresh_tokens
+                SET used_at = COALESCE(used_at, NOW())
+                WHERE token_hash = $1
+            "#,
+            token_hash
+        )
+            .execute(&mut *tx)
+            .await?;
+
+        query!(
+            r#"
+                UPDATE sessions
+                SET expires_at = $1, last_seen_at = NOW(), refreshed_at = NOW()
+                WHERE id = $2
+            "#,
+            expires_at,
+            current.session_id
+        )
+            .execute(&mut *tx)
+            .await?;
+
+        let refresh_token = generate_token();
+
+        query!(
+            r#"
+                INSERT INTO refresh_tokens (token_hash, session_id, expires_at)
+                VALUES ($1, $2, $3)
+            "#,
+            hash_token(&refresh_token),
+            current.session_id,
+            expires_at
+        )
+            .execute(&mut *tx)
+            .await?;
+
+        tx.commit()
+            .await?;
+
+        Ok((
+            Self {
+                id: current.session_id,
+                user_id: current.user_id
+            },
+            refresh_token
+        ))
+    }
+
+    pub async fn revoke(id: Uuid) -> SessionResult<()> {
+        query!(
+            r#"
+                UPDATE sessions
+                SET revoked_at = NOW()
+                WHERE id = $1
+                AND revoked_at IS NULL
+            "#,
+            id
+        )
+            .execute(db!())
+            .await?;
+
+        ACTIVE_CACHE.invalidate(&id);
+
+        Ok(())
+    }
+
+    // only revokes sessions of the user, returns whether there was one to revoke.
+    pub async fn revoke_owned(user_id: i32, id: Uuid) -> SessionResult<bool> {
+        let revoked = query!(
+            r#"
+                UPDATE sessions
+                SET revoked_at = NOW()
+                WHERE id = $1
+                AND user_id = $2
+                AND revoked_at IS NULL
+                AND expires_at > NOW()
+            "#,
+            id,
+            user_id
+        )
+            .execute(db!())
+            .await?
+            .rows_affected();
+
+        ACTIVE_CACHE.invalidate(&id);
+
+        Ok(revoked > 0)
+    }
+
+    // returns the ids of the sessions that were revoked.
+    pub async fn revoke_all(executor: impl PgExecutor<'_>, user_id: i32) -> SessionResult<Vec<Uuid>> {
+        let revoked = query!(
+            r#"
+                UPDATE sessions
+                SET revoked_at = NOW()
+                WHERE user_id = $1
+                AND revoked_at IS NULL
+                RETURNING id
+            "#,
+            user_id
+        )
+            .fetch_all(executor)
+            .await?
+            .into_iter()
+            .map(|session| session.id)
+            .collect::<Vec<_>>();
+
+        for id in &revoked {
+            ACTIVE_CACHE.invalidate(id);
+        }
+
+        Ok(revoked)
+    }
+
+    // like revoke_all but keeps the session the request was made with.
+    pub async fn revoke_others(executor: impl PgExecutor<'_>, user_id: i32, keep: Uuid) -> SessionResult<Vec<Uuid>> {
+        let revoked = query!(
+            r#"
+                UPDATE sessions
+                SET revoked_at = NOW()
+                WHERE user_id = $1
+                AND id <> $2
+                AND revoked_at IS NULL
+                RETURNING id
+            "#,
+            user_id,
+            keep
+        )
+            .fetch_all(executor)
+            .await?
+            .into_iter()
+            .map(|session| session.id)
+            .collect::<Vec<_>>();
+
+        for id in &revoked {
+            ACTIVE_CACHE.invalidate(id);
+        }
+
+        Ok(revoked)
+    }
+
+    pub async fn list(user_id: i32) -> SessionResult<Vec<SessionDetails>> {
+        Ok(query_as!(
+            SessionDetails,
+            r#"
+                SELECT id, ip, user_agent, created_at, last_seen_at, expires_at
+                FROM sessions
+                WHERE user_id = $1
+                AND revoked_at IS NULL
+                AND expires_at > NOW()
+                ORDER BY last_seen_at DESC
+            "#,
+            user_id
+        )
+            .fetch_all(db!())
+            .await?)
+    }
+
+    // checked on every authenticated request, revocations may take
+    // a few seconds to be seen by other instances.
+    pub async fn is_active(id: Uuid) -> SessionResult<bool> {
+        if let Some(active) = ACTIVE_CACHE.get(&id) {
+            return Ok(active);
+        }
+
+        // the activity is only recorded when the cache expires,
+        // so last_seen_at is precise to the minute at best.
+        query!(
+            r#"
+                UPDATE sessions
+                SET last_seen_at = NOW()
+                WHERE id = $1
+                AND last_seen_at < NOW() - INTERVAL '1 minute'
+            "#,
+            id
+        )
+            .execute(db!())
+            .await?;
+
+        let active = query!(
+            r#"
+                SELECT EXISTS (
+                    SELECT 1
+                    FROM sessions
+                    WHERE id = $1
+                    AND revoked_at IS NULL
+                    AND expires_at > NOW()
+                )
+            "#,
+            id
+        )
+            .fetch_one(db!())
+            .await?
+            .exists
+            .unwrap_or(false);
+
+        ACTIVE_CACHE.insert(id, active);
+
+        Ok(active)
+    }
+
+    // when the last access token issued for the session expires, none when the
+    // session isn't active anymore. sockets live as long as that token does.
+    pub async fn access_expires_at(id: Uuid) -> SessionResult<Option<OffsetDateTime>> {
+        Ok(
+            query!(
+                r#"
+                    SELECT refreshed_at
+                    FROM sessions
+                    WHERE id = $1
+                    AND revoked_at IS NULL
+                    AND expires_at > NOW()
+                "#,
+                id
+            )
+                .fetch_optional(db!())
+                .await?
+                .map(|session| session.refreshed_at.add(ACCESS_TOKEN_LIFETIME))
+        )
+    }
+
+    pub fn id(&self) -> Uuid {
+        self.id
+    }
+
+    pub fn user_id(&self) -> i32 {
+        self.user_id
+    }
+}
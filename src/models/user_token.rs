@@ -0,0 +1,125 @@
+use std::ops::Add;
+use sqlx::{query, Error as SqlxError};
+use thiserror::Error;
+use time::{Duration, OffsetDateTime};
+use crate::{db, helpers::{database::connection::DbConnectionError, tokens::{generate_token, hash_token}}};
+
+#[derive(Error, Debug)]
+pub enum TokenError {
+    #[error("{0:#}")]
+    DbQuery(#[from] SqlxError),
+
+    #[error("{0:#}")]
+    DbConn(#[from] DbConnectionError)
+}
+
+type TokenResult<R> = Result<R, TokenError>;
+
+// what a token can be used for, a token only works for the purpose it was issued for.
+#[derive(Clone, Copy)]
+pub enum TokenPurpose {
+    Activation,
+    PasswordReset,
+    EmailChange
+}
+
+impl TokenPurpose {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Activation => "activation",
+            Self::PasswordReset => "password_reset",
+            Self::EmailChange => "email_change"
+        }
+    }
+
+    pub fn lifetime(&self) -> Duration {
+        match self {
+            Self::Activation => Duration::days(2),
+            Self::PasswordReset => Duration::hours(1),
+            Self::EmailChange => Duration::days(1)
+        }
+    }
+}
+
+// single use tokens sent to the users, only their hashes are stored.
+pub struct UserToken;
+
+impl UserToken {
+    // issuing a token invalidates the ones issued before for the same purpose.
+    pub async fn issue(user_id: i32, purpose: TokenPurpose) -> TokenResult<String> {
+        Self::issue_with_payload(user_id, purpose, None)
+            .await
+    }
+
+    // the payload is kept along the token, like the address an email change is for.
+    pub async fn issue_with_payload(user_id: i32, purpose: TokenPurpose, payload: Option<&str>) -> TokenResult<String> {
+        let token = generate_token();
+
+        let mut tx = db!()
+            .begin()
+            .await?;
+
+        query!(
+            r#"
+                DELETE FROM user_tokens
+                WHERE user_id = $1
+                AND purpose = $2
+            "#,
+            user_id,
+            purpose.as_str()
+        )
+            .execute(&mut *tx)
+            .await?;
+
+        query!(
+            r#"
+                INSERT INTO user_tokens (token_hash, user_id, purpose, payload, expires_at)
+                VALUES ($1, $2, $3, $4, $5)
+            "#,
+            hash_token(&token),
+            user_id,
+            purpose.as_str(),
+            payload,
+            OffsetDateTime::now_utc()
+                .add(purpose.lifetime())
+        )
+            .execute(&mut *tx)
+            .await?;
+
+        tx.commit()
+            .await?;
+
+        Ok(token)
+    }
+
+    // marks the token as used, returns the user it was issued for
+    // or none if it doesn't exist, expired or was already used.
+    pub async fn consume(token: &str, purpose: TokenPurpose) -> TokenResult<Option<i32>> {
+        Ok(
+            Self::consume_with_payload(token, purpose)
+                .await?
+                .map(|(user_id, _)| user_id)
+        )
+    }
+
+    pub async fn consume_with_payload(token: &str, purpose: TokenPurpose) -> TokenResult<Option<(i32, Option<String>)>> {
+        Ok(
+            query!(
+                r#"
+                    UPDATE user_tokens
+                    SET consumed_at = NOW()
+                    WHERE token_hash = $1
+                    AND purpose = $2
+                    AND consumed_at IS NULL
+                    AND expires_at > NOW()
+                    RETURNING user_id, payload
+                "#,
+                hash_token(token),
+                purpose.as_str()
+            )
+                .fetch_optional(db!())
+                .await?
+                .map(|token| (token.user_id, token.payload))
+        )
+    }
+}
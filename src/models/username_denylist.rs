@@ -0,0 +1,109 @@
+use serde::Serialize;
+use sqlx::{query, query_as, Error as SqlxError};
+use thiserror::Error;
+use time::OffsetDateTime;
+use crate::{db, helpers::{database::connection::DbConnectionError, validation::canonical_username}};
+
+#[derive(Error, Debug)]
+pub enum DenylistError {
+    #[error("{0:#}")]
+    DbQuery(#[from] SqlxError),
+
+    #[error("{0:#}")]
+    DbConn(#[from] DbConnectionError),
+
+    #[error("Denied terms must be between 1 and 25 characters.")]
+    InvalidTerm,
+
+    #[error("This term is already denied.")]
+    AlreadyDenied
+}
+
+type DenylistResult<R> = Result<R, DenylistError>;
+
+// a term usernames can't contain, kept by the admins on top of the reserved names.
+// terms are compared by their canonical form, so their lookalikes are denied too.
+#[derive(Serialize)]
+pub struct DeniedTerm {
+    term: String,
+    #[serde(with = "time::serde::rfc3339")]
+    created_at: OffsetDateTime
+}
+
+pub struct UsernameDenylist;
+
+impl UsernameDenylist {
+    pub async fn list() -> DenylistResult<Vec<DeniedTerm>> {
+        Ok(query_as!(
+            DeniedTerm,
+            r#"
+                SELECT term, created_at
+                FROM username_denylist
+                ORDER BY created_at
+            "#
+        )
+            .fetch_all(db!())
+            .await?)
+    }
+
+    // the users that already have a name with the term keep it.
+    pub async fn add(term: &str) -> DenylistResult<DeniedTerm> {
+        let term = term.trim();
+
+        if term.is_empty() || term.chars().count() > 25 {
+            return Err(DenylistError::InvalidTerm);
+        }
+
+        query_as!(
+            DeniedTerm,
+            r#"
+                INSERT INTO username_denylist (canonical, term)
+                VALUES ($1, $2)
+                RETURNING term, created_at
+            "#,
+            canonical_username(term),
+            term
+        )
+            .fetch_one(db!())
+            .await
+            .map_err(|err| match err.as_database_error() {
+                Some(db_err) if db_err.is_unique_violation() => DenylistError::AlreadyDenied,
+                _ => DenylistError::DbQuery(err)
+            })
+    }
+
+    // returns whether the term was denied.
+    pub async fn remove(term: &str) -> DenylistResult<bool> {
+        Ok(
+            query!(
+                r#"
+                    DELETE FROM username_denylist
+                    WHERE canonical = $1
+                "#,
+                canonical_username(term.trim())
+            )
+                .execute(db!())
+                .await?
+                .rows_affected() > 0
+        )
+    }
+
+    pub async fn denies(username: &str) -> DenylistResult<bool> {
+        Ok(
+            query!(
+                r#"
+                    SELECT EXISTS (
+                        SELECT 1
+                        FROM username_denylist
+                        WHERE STRPOS($1, canonical) > 0
+                    )
+                "#,
+                canonical_username(username)
+            )
+                .fetch_one(db!())
+                .await?
+                .exists
+                .unwrap_or(false)
+        )
+    }
+}
@@ -0,0 +1,38 @@
+use actix_web::{delete, get, HttpResponse};
+use serde::Deserialize;
+use crate::{helpers::http::{api_error::ApiError, cookies::removal_cookies, envelope::Envelope, form_or_json::FormOrJson, socket_registry}, models::{account_export::AccountExport, user::User}};
+
+#[derive(Deserialize)]
+struct DeleteAccountParams {
+    #[serde(default)]
+    password: String
+}
+
+#[get("/export")]
+pub async fn export_account(user: User) -> Result<HttpResponse, ApiError> {
+    Ok(HttpResponse::Ok()
+        .insert_header(("Content-Disposition", "attachment; filename=\"canvadot-account.json\""))
+        .json(Envelope::data(AccountExport::collect(&user).await?)))
+}
+
+// this can't be undone, the pixels stay on the canvas without an author.
+#[delete("")]
+pub async fn delete_account(user: User, params: FormOrJson<DeleteAccountParams>) -> Result<HttpResponse, ApiError> {
+    if user.has_password() && !user.verify_password(&params.password) {
+        return Err(ApiError::forbidden("The password is not correct."));
+    }
+
+    let revoked = user.delete()
+        .await?;
+
+    socket_registry::close_revoked(&revoked)
+        .await;
+
+    let mut res = HttpResponse::Ok();
+
+    for cookie in removal_cookies() {
+        res.cookie(cookie);
+    }
+
+    Ok(res.json(Envelope::ok()))
+}
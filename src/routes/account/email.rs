@@ -0,0 +1,44 @@
+use actix_web::{post, web::Query, HttpResponse};
+use serde::Deserialize;
+use crate::{helpers::{http::{api_error::ApiError, envelope::Envelope, form_or_json::FormOrJson}, validation::validate_email}, models::user::User};
+
+#[derive(Deserialize)]
+struct ChangeEmailParams {
+    email: String,
+    #[serde(default)]
+    password: String
+}
+
+#[derive(Deserialize)]
+struct ConfirmEmailParams {
+    #[serde(rename = "t")]
+    token: String
+}
+
+// a confirmation link is sent to the new address, the email changes once it's opened.
+#[post("/email")]
+pub async fn change_email(user: User, params: FormOrJson<ChangeEmailParams>) -> Result<HttpResponse, ApiError> {
+    let ChangeEmailParams { email, password } = params.into_inner();
+
+    if user.has_password() && !user.verify_password(&password) {
+        return Err(ApiError::forbidden("The password is not correct."));
+    }
+
+    validate_email(&email)
+        .map_err(ApiError::bad_request)?;
+
+    user.request_email_change(&email)
+        .await?;
+
+    Ok(HttpResponse::Accepted()
+        .json(Envelope::ok()))
+}
+
+#[post("/email/confirm")]
+pub async fn confirm_email(params: Query<ConfirmEmailParams>) -> Result<HttpResponse, ApiError> {
+    User::confirm_email_change(&params.token)
+        .await?;
+
+    Ok(HttpResponse::Ok()
+        .json(Envelope::ok()))
+}
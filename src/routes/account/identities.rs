@@ -0,0 +1,19 @@
+use actix_web::{delete, get, web::Path, HttpResponse};
+use crate::{helpers::http::{api_error::ApiError, envelope::Envelope}, models::{oauth_identity::OAuthIdentity, user::User}};
+
+// accounts are linked through /auth/oauth/{provider}?link=true
+#[get("/identities")]
+pub async fn list_identities(user: User) -> Result<HttpResponse, ApiError> {
+    Ok(HttpResponse::Ok()
+        .json(Envelope::data(OAuthIdentity::of_user(user.id()).await?)))
+}
+
+#[delete("/identities/{provider}")]
+pub async fn unlink_identity(user: User, provider: Path<String>) -> Result<HttpResponse, ApiError> {
+    if !OAuthIdentity::unlink(user.id(), &provider, user.has_password()).await? {
+        return Err(ApiError::not_found("This provider is not linked."));
+    }
+
+    Ok(HttpResponse::Ok()
+        .json(Envelope::ok()))
+}
@@ -0,0 +1,7 @@
+pub mod password;
+pub mod email;
+pub mod username;
+pub mod data;
+pub mod tokens;
+pub mod identities;
+pub mod two_factor;
@@ -0,0 +1,34 @@
+use actix_web::{post, HttpResponse};
+use serde::Deserialize;
+use crate::{helpers::{http::{api_error::ApiError, envelope::Envelope, form_or_json::FormOrJson, socket_registry}, validation::validate_password}, models::user::SessionUser};
+
+#[derive(Deserialize)]
+struct ChangePasswordParams {
+    // users signed up with an external provider set their first password without one.
+    #[serde(default)]
+    current_password: String,
+    new_password: String
+}
+
+// the rest of devices are logged out, this one stays logged in.
+#[post("/password")]
+pub async fn change_password(session: SessionUser, params: FormOrJson<ChangePasswordParams>) -> Result<HttpResponse, ApiError> {
+    let ChangePasswordParams { current_password, new_password } = params.into_inner();
+
+    if session.user().has_password() && !session.user().verify_password(&current_password) {
+        return Err(ApiError::forbidden("The current password is not correct."));
+    }
+
+    validate_password(&new_password)
+        .map_err(ApiError::bad_request)?;
+
+    let revoked = session.user()
+        .change_password(&new_password, session.session_id())
+        .await?;
+
+    socket_registry::close_revoked(&revoked)
+        .await;
+
+    Ok(HttpResponse::Ok()
+        .json(Envelope::ok()))
+}
@@ -0,0 +1,66 @@
+use actix_web::{delete, get, post, web::Path, HttpResponse};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use crate::{helpers::http::{api_error::ApiError, envelope::Envelope, form_or_json::FormOrJson, socket_registry}, models::{api_token::{ApiScope, ApiToken}, user::User}};
+
+#[derive(Deserialize)]
+struct CreateTokenParams {
+    name: String,
+    // comma separated, like canvas:read,canvas:write
+    scopes: String
+}
+
+#[derive(Serialize)]
+struct CreatedToken {
+    #[serde(flatten)]
+    details: ApiToken,
+    token: String
+}
+
+#[get("/tokens")]
+pub async fn list_tokens(user: User) -> Result<HttpResponse, ApiError> {
+    Ok(HttpResponse::Ok()
+        .json(Envelope::data(ApiToken::list(user.id()).await?)))
+}
+
+// the token is only shown in this response.
+#[post("/tokens")]
+pub async fn create_token(user: User, params: FormOrJson<CreateTokenParams>) -> Result<HttpResponse, ApiError> {
+    let CreateTokenParams { name, scopes } = params.into_inner();
+
+    let name = name.trim();
+
+    if name.is_empty() || name.chars().count() > 50 {
+        return Err(ApiError::bad_request("Token names must be between 1 and 50 characters."));
+    }
+
+    let scopes = scopes
+        .split(',')
+        .filter(|scope| !scope.trim().is_empty())
+        .map(ApiScope::try_from)
+        .collect::<Result<Vec<_>, _>>()?;
+
+    if scopes.is_empty() {
+        return Err(ApiError::bad_request("A token needs at least one scope."));
+    }
+
+    let (details, token) = ApiToken::create(user.id(), name, &scopes)
+        .await?;
+
+    Ok(HttpResponse::Created()
+        .json(Envelope::data(CreatedToken { details, token })))
+}
+
+// the sockets opened with the token are closed too.
+#[delete("/tokens/{id}")]
+pub async fn revoke_token(user: User, id: Path<Uuid>) -> Result<HttpResponse, ApiError> {
+    if !ApiToken::revoke(user.id(), *id).await? {
+        return Err(ApiError::not_found("The token does not exist."));
+    }
+
+    socket_registry::close_revoked(&[*id])
+        .await;
+
+    Ok(HttpResponse::Ok()
+        .json(Envelope::ok()))
+}
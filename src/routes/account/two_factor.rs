@@ -0,0 +1,121 @@
+use std::time::Duration;
+use actix_web::{delete, get, post, HttpResponse};
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use crate::{db, helpers::http::{api_error::ApiError, envelope::Envelope, form_or_json::FormOrJson, rate_limit::RateLimiter, socket_registry}, models::{role::Role, two_factor::TwoFactor, user::{SessionUser, User}, user_session::UserSession}};
+
+lazy_static! {
+    // codes entered while logged in, the ones of the login are throttled with the login attempts.
+    static ref CODE_LIMITER: RateLimiter<i32> = RateLimiter::new(5, Duration::from_secs(5 * 60));
+}
+
+#[derive(Deserialize)]
+struct CodeParams {
+    code: String
+}
+
+#[derive(Deserialize)]
+struct DisableParams {
+    code: String,
+    #[serde(default)]
+    password: String
+}
+
+#[derive(Serialize)]
+struct TwoFactorStatus {
+    enabled: bool,
+    recovery_codes_left: i64
+}
+
+#[derive(Serialize)]
+struct EnrollmentDetails {
+    secret: String,
+    // rendered as a qr code by the front-end.
+    uri: String
+}
+
+#[derive(Serialize)]
+struct RecoveryCodes {
+    recovery_codes: Vec<String>
+}
+
+fn hit_code_limit(user_id: i32) -> Result<(), ApiError> {
+    CODE_LIMITER.hit(user_id)
+        .map_err(|retry| ApiError::too_many_requests("Too many codes entered, try again later.", retry))
+}
+
+#[get("/two-factor")]
+pub async fn two_factor_status(user: User) -> Result<HttpResponse, ApiError> {
+    Ok(HttpResponse::Ok()
+        .json(Envelope::data(TwoFactorStatus {
+            enabled: TwoFactor::is_enabled(user.id()).await?,
+            recovery_codes_left: TwoFactor::recovery_codes_left(user.id()).await?
+        })))
+}
+
+// nothing changes until the enrollment is confirmed with a code of the app.
+#[post("/two-factor")]
+pub async fn enroll_two_factor(user: User) -> Result<HttpResponse, ApiError> {
+    let enrollment = TwoFactor::enroll(user.id(), user.name())
+        .await?;
+
+    Ok(HttpResponse::Ok()
+        .json(Envelope::data(EnrollmentDetails {
+            secret: enrollment.secret,
+            uri: enrollment.uri
+        })))
+}
+
+// the rest of devices are logged out, since they logged in without a code.
+#[post("/two-factor/confirm")]
+pub async fn confirm_two_factor(session: SessionUser, params: FormOrJson<CodeParams>) -> Result<HttpResponse, ApiError> {
+    hit_code_limit(session.user().id())?;
+
+    let recovery_codes = TwoFactor::confirm(session.user().id(), &params.code)
+        .await?;
+
+    let revoked = UserSession::revoke_others(db!(), session.user().id(), session.session_id())
+        .await?;
+
+    socket_registry::close_revoked(&revoked)
+        .await;
+
+    Ok(HttpResponse::Ok()
+        .json(Envelope::data(RecoveryCodes { recovery_codes })))
+}
+
+#[post("/two-factor/recovery-codes")]
+pub async fn regenerate_recovery_codes(user: User, params: FormOrJson<CodeParams>) -> Result<HttpResponse, ApiError> {
+    hit_code_limit(user.id())?;
+
+    TwoFactor::verify(user.id(), &params.code)
+        .await?;
+
+    Ok(HttpResponse::Ok()
+        .json(Envelope::data(RecoveryCodes {
+            recovery_codes: TwoFactor::regenerate_recovery_codes(user.id()).await?
+        })))
+}
+
+// users with a role that requires two-factor authentication can't disable it.
+#[delete("/two-factor")]
+pub async fn disable_two_factor(user: User, params: FormOrJson<DisableParams>) -> Result<HttpResponse, ApiError> {
+    if user.has_password() && !user.verify_password(&params.password) {
+        return Err(ApiError::forbidden("The password is not correct."));
+    }
+
+    if Role::require_two_factor(&Role::of_user(user.id()).await?).await? {
+        return Err(ApiError::conflict("One of your roles requires two-factor authentication."));
+    }
+
+    hit_code_limit(user.id())?;
+
+    TwoFactor::verify(user.id(), &params.code)
+        .await?;
+
+    TwoFactor::disable(user.id())
+        .await?;
+
+    Ok(HttpResponse::Ok()
+        .json(Envelope::ok()))
+}
@@ -0,0 +1,31 @@
+use actix_web::{post, HttpResponse};
+use serde::Deserialize;
+use crate::{helpers::{http::{api_error::ApiError, envelope::Envelope, form_or_json::FormOrJson}, validation::{normalize_username, validate_username}}, models::{user::User, username_denylist::UsernameDenylist}};
+
+#[derive(Deserialize)]
+struct ChangeUsernameParams {
+    username: String
+}
+
+#[post("/username")]
+pub async fn change_username(user: User, params: FormOrJson<ChangeUsernameParams>) -> Result<HttpResponse, ApiError> {
+    let ChangeUsernameParams { username } = params.into_inner();
+    let username = normalize_username(&username);
+
+    validate_username(&username)
+        .map_err(ApiError::bad_request)?;
+
+    if UsernameDenylist::denies(&username).await? {
+        return Err(ApiError::bad_request("This username is not allowed."));
+    }
+
+    if username == user.username() {
+        return Err(ApiError::bad_request("This is already your username."));
+    }
+
+    user.change_username(&username)
+        .await?;
+
+    Ok(HttpResponse::Ok()
+        .json(Envelope::ok()))
+}
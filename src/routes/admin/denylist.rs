@@ -0,0 +1,31 @@
+use actix_web::{delete, get, post, web::Path, HttpResponse};
+use serde::Deserialize;
+use crate::{helpers::http::{api_error::ApiError, envelope::Envelope, form_or_json::FormOrJson, require_role::{Admin, Moderator, RequireRole}}, models::username_denylist::UsernameDenylist};
+
+#[derive(Deserialize)]
+struct DenyParams {
+    term: String
+}
+
+#[get("/username-denylist")]
+pub async fn denied_terms(_moderator: RequireRole<Moderator>) -> Result<HttpResponse, ApiError> {
+    Ok(HttpResponse::Ok()
+        .json(Envelope::data(UsernameDenylist::list().await?)))
+}
+
+// new usernames containing the term or a lookalike of it are refused.
+#[post("/username-denylist")]
+pub async fn deny_term(_admin: RequireRole<Admin>, params: FormOrJson<DenyParams>) -> Result<HttpResponse, ApiError> {
+    Ok(HttpResponse::Created()
+        .json(Envelope::data(UsernameDenylist::add(&params.term).await?)))
+}
+
+#[delete("/username-denylist/{term}")]
+pub async fn allow_term(_admin: RequireRole<Admin>, term: Path<String>) -> Result<HttpResponse, ApiError> {
+    if !UsernameDenylist::remove(&term).await? {
+        return Err(ApiError::not_found("The term is not denied."));
+    }
+
+    Ok(HttpResponse::Ok()
+        .json(Envelope::ok()))
+}
@@ -0,0 +1,67 @@
+use actix_web::{delete, get, post, put, web::Path, HttpResponse};
+use serde::Deserialize;
+use crate::{helpers::http::{api_error::ApiError, envelope::Envelope, form_or_json::FormOrJson, require_role::{Admin, Moderator, RequireRole}}, models::{role::{Role, ADMIN_ROLE}, user::User}};
+
+#[derive(Deserialize)]
+struct TwoFactorParams {
+    required: bool
+}
+
+async fn find_user(username: &String) -> Result<User, ApiError> {
+    User::find_by_username(username)
+        .await?
+        .ok_or(ApiError::not_found("The user does not exist."))
+}
+
+#[get("/users/{username}/roles")]
+pub async fn user_roles(_moderator: RequireRole<Moderator>, username: Path<String>) -> Result<HttpResponse, ApiError> {
+    let user = find_user(&username)
+        .await?;
+
+    Ok(HttpResponse::Ok()
+        .json(Envelope::data(Role::of_user(user.id()).await?)))
+}
+
+// the guards and sockets read the current roles, the change is seen within seconds.
+#[post("/users/{username}/roles/{role}")]
+pub async fn grant_role(_admin: RequireRole<Admin>, path: Path<(String, String)>) -> Result<HttpResponse, ApiError> {
+    let (username, role) = path.into_inner();
+
+    let user = find_user(&username)
+        .await?;
+
+    Role::grant(user.id(), &role)
+        .await?;
+
+    Ok(HttpResponse::Ok()
+        .json(Envelope::ok()))
+}
+
+#[delete("/users/{username}/roles/{role}")]
+pub async fn revoke_role(admin: RequireRole<Admin>, path: Path<(String, String)>) -> Result<HttpResponse, ApiError> {
+    let (username, role) = path.into_inner();
+
+    let user = find_user(&username)
+        .await?;
+
+    if user.id() == admin.user().id() && role == ADMIN_ROLE {
+        return Err(ApiError::bad_request("You can't remove your own admin role."));
+    }
+
+    Role::revoke(user.id(), &role)
+        .await?;
+
+    Ok(HttpResponse::Ok()
+        .json(Envelope::ok()))
+}
+
+// users with the role that didn't enable two-factor authentication
+// can't use it until they do, they keep the permissions of the rest of roles.
+#[put("/roles/{role}/two-factor")]
+pub async fn require_two_factor(_admin: RequireRole<Admin>, role: Path<String>, params: FormOrJson<TwoFactorParams>) -> Result<HttpResponse, ApiError> {
+    Role::set_requires_two_factor(&role, params.required)
+        .await?;
+
+    Ok(HttpResponse::Ok()
+        .json(Envelope::ok()))
+}
@@ -1,7 +1,12 @@
-use actix_web::{post, web::Query, HttpResponse, Responder};
+use std::time::Duration;
+use actix_web::{post, web::Query, HttpResponse};
+use lazy_static::lazy_static;
 use serde::Deserialize;
-use crate::{grv, models::user::User};
+use crate::{helpers::http::{api_error::ApiError, envelope::Envelope, rate_limit::RateLimiter}, models::user::User};
 
+lazy_static! {
+    static ref RESEND_LIMITER: RateLimiter<i32> = RateLimiter::new(3, Duration::from_secs(60 * 60));
+}
 
 #[derive(Deserialize)]
 struct ActivateParams {
@@ -10,9 +15,26 @@ struct ActivateParams {
 }
 
 #[post("/activate")]
-pub async fn activate(params: Query<ActivateParams>) -> impl Responder {
-    grv!(User::activate(&params.token).await);
+pub async fn activate(params: Query<ActivateParams>) -> Result<HttpResponse, ApiError> {
+    User::activate(&params.token)
+        .await?;
+
+    Ok(HttpResponse::Ok()
+        .json(Envelope::ok()))
+}
+
+#[post("/activate/resend")]
+pub async fn resend_activation(user: User) -> Result<HttpResponse, ApiError> {
+    if user.activated() {
+        return Err(ApiError::conflict("The account is already activated."));
+    }
+
+    RESEND_LIMITER.hit(user.id())
+        .map_err(|retry| ApiError::too_many_requests("Too many activation emails requested, try again later.", retry))?;
+
+    user.send_activation()
+        .await?;
 
-    HttpResponse::Ok()
-        .finish()
+    Ok(HttpResponse::Accepted()
+        .json(Envelope::ok()))
 }
@@ -1,7 +1,6 @@
-use std::ops::Add;
-use actix_web::{cookie::{time::{Duration, OffsetDateTime}, Cookie}, post, web::Form, HttpResponse, Responder};
+use actix_web::{post, HttpRequest, HttpResponse};
 use serde::{Deserialize, Serialize};
-use crate::{grv, models::user::User};
+use crate::{helpers::http::{api_error::ApiError, client_ip::client_ip, cookies::{access_cookie, refresh_cookie, two_factor_cookie, two_factor_removal_cookie, TWO_FACTOR_COOKIE}, envelope::Envelope, form_or_json::FormOrJson}, models::{login_attempt::LoginAttempt, two_factor::{TwoFactor, TwoFactorError}, user::User, user_session::SessionOrigin}};
 
 #[derive(Serialize, Deserialize)]
 struct LoginParams {
@@ -9,28 +8,88 @@ struct LoginParams {
     password: String
 }
 
+#[derive(Serialize)]
+struct LoginStep {
+    two_factor_required: bool
+}
+
+#[derive(Deserialize)]
+struct TwoFactorParams {
+    // a code of the authenticator app or a recovery code.
+    code: String
+}
+
+// users with two-factor authentication enabled get a 202 and a challenge cookie,
+// the login finishes by sending the code to /auth/login/two-factor.
 #[post("/login")]
-pub async fn login(params: Form<LoginParams>) -> impl Responder {
+pub async fn login(req: HttpRequest, params: FormOrJson<LoginParams>) -> Result<HttpResponse, ApiError> {
     let LoginParams { email, password } = params.into_inner();
+    let ip = client_ip(&req);
 
-    let user = match grv!(User::login(email, password).await) {
-        Some(user) => user,
-        None => {
-            return HttpResponse::Unauthorized()
-                .body("The account does not exist.");
-        }
+    let attempt = LoginAttempt::start(&email, &ip)
+        .await?;
+
+    let Some(user) = User::login(email, password).await?
+    else {
+        return Err(ApiError::unauthorized("The account does not exist."));
     };
 
-    let mut cookie = Cookie::new("Session", grv!(user.jwt()));
+    // the attempt only counts as successful once the code is entered,
+    // so knowing the password doesn't reset the throttling of the codes.
+    if TwoFactor::is_enabled(user.id()).await? {
+        return Ok(HttpResponse::Accepted()
+            .cookie(two_factor_cookie(TwoFactor::challenge(user.id())?))
+            .json(Envelope::data(LoginStep { two_factor_required: true })));
+    }
+
+    attempt.succeed()
+        .await?;
+
+    let (access_token, refresh_token) = user.start_session(SessionOrigin::from(&req))
+        .await?;
+
+    Ok(HttpResponse::Ok()
+        .cookie(access_cookie(access_token))
+        .cookie(refresh_cookie(refresh_token))
+        .json(Envelope::ok()))
+}
+
+#[post("/login/two-factor")]
+pub async fn login_two_factor(req: HttpRequest, params: FormOrJson<TwoFactorParams>) -> Result<HttpResponse, ApiError> {
+    let user_id = req.cookie(TWO_FACTOR_COOKIE)
+        .and_then(|cookie| TwoFactor::challenged_user(cookie.value()))
+        .ok_or(ApiError::unauthorized("The login expired, log in again."))?;
+
+    let user = User::find(user_id)
+        .await?
+        .ok_or(ApiError::unauthorized("The login expired, log in again."))?;
+
+    let ip = client_ip(&req);
+
+    let attempt = LoginAttempt::start(user.email(), &ip)
+        .await?;
+
+    match TwoFactor::verify(user.id(), &params.code).await {
+        Ok(()) => {},
+
+        Err(err @ TwoFactorError::InvalidCode) => {
+            return Err(ApiError::unauthorized(err));
+        },
+
+        Err(err) => {
+            return Err(err.into());
+        }
+    }
 
-    cookie.set_expires(
-        OffsetDateTime::now_utc()
-            .add(Duration::hours(12))
-    );
+    attempt.succeed()
+        .await?;
 
-    cookie.set_path("/");
+    let (access_token, refresh_token) = user.start_session(SessionOrigin::from(&req))
+        .await?;
 
-    HttpResponse::Ok()
-        .cookie(cookie)
-        .finish()
+    Ok(HttpResponse::Ok()
+        .cookie(access_cookie(access_token))
+        .cookie(refresh_cookie(refresh_token))
+        .cookie(two_factor_removal_cookie())
+        .json(Envelope::ok()))
 }
@@ -0,0 +1,34 @@
+use actix_web::{post, HttpResponse};
+use uuid::Uuid;
+use crate::{helpers::http::{api_error::ApiError, cookies::removal_cookies, envelope::Envelope, socket_registry}, models::{user::SessionUser, user_session::UserSession}};
+
+#[post("/logout")]
+pub async fn logout(session: SessionUser) -> Result<HttpResponse, ApiError> {
+    UserSession::revoke(session.session_id())
+        .await?;
+
+    Ok(closed(&[session.session_id()]).await)
+}
+
+// logs out every device the user is logged in, this one included, and revokes its api tokens.
+#[post("/logout/all")]
+pub async fn logout_all(session: SessionUser) -> Result<HttpResponse, ApiError> {
+    let revoked = session.user()
+        .log_out_everywhere()
+        .await?;
+
+    Ok(closed(&revoked).await)
+}
+
+async fn closed(session_ids: &[Uuid]) -> HttpResponse {
+    socket_registry::close_revoked(session_ids)
+        .await;
+
+    let mut res = HttpResponse::Ok();
+
+    for cookie in removal_cookies() {
+        res.cookie(cookie);
+    }
+
+    res.json(Envelope::ok())
+}
@@ -3,3 +3,8 @@ pub mod login;
 pub mod register;
 pub mod user;
 pub mod activate;
+pub mod refresh;
+pub mod logout;
+pub mod password;
+pub mod oauth;
+pub mod sessions;
@@ -0,0 +1,541 @@
+use std::ops::Add;
+use actix_web::{get, post, web::{Path, Query}, HttpRequest, HttpResponse};
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use time::{Duration, OffsetDateTime};
+use crate::{helpers::{http::{api_error::ApiError, cookies::{access_cookie, oauth_removal_cookie, oauth_signup_cookie, oauth_state_cookie, refresh_cookie, two_factor_cookie, OAUTH_SIGNUP_COOKIE, OAUTH_STATE_COOKIE}, envelope::{Envelope, FieldErrors}, form_or_json::FormOrJson, jwt::{decode_jwt, encode_jwt, Claims}}, mail::templates::app_url, oauth::provider::{provider, ExternalIdentity, Provider}, tokens::generate_token, validation::normalize_username}, models::{oauth_identity::OAuthIdentity, two_factor::TwoFactor, user::{MaybeUser, User}, user_session::SessionOrigin}};
+use super::register::signup_field_errors;
+
+// how long the user has to choose a username after authorizing.
+const SIGNUP_LIFETIME: Duration = Duration::minutes(30);
+
+#[derive(Deserialize)]
+struct AuthorizeParams {
+    // links the account to the logged in user instead of logging in with it.
+    #[serde(default)]
+    link: bool
+}
+
+#[derive(Deserialize)]
+struct CallbackParams {
+    code: Option<String>,
+    state: Option<String>
+}
+
+#[derive(Deserialize)]
+struct CompleteParams {
+    username: String,
+    // only needed when the provider didn't share an email.
+    email: Option<String>
+}
+
+// signed into the signup cookie, so the identity can't be tampered with
+// while the user chooses a username.
+#[derive(Serialize, Deserialize)]
+struct SignupClaims {
+    provider: String,
+    subject: String,
+    email: Option<String>,
+    email_verified: bool
+}
+
+// users with two-factor authentication enabled are sent to enter the code,
+// the provider only replaces the password.
+async fn log_in(req: &HttpRequest, user: &User) -> Result<HttpResponse, ApiError> {
+    if TwoFactor::is_enabled(user.id()).await? {
+        return Ok(HttpResponse::Found()
+            .insert_header(("Location", format!("{}/login/two-factor", app_url())))
+            .cookie(two_factor_cookie(TwoFactor::challenge(user.id())?))
+            .cookie(oauth_removal_cookie(OAUTH_STATE_COOKIE))
+            .finish());
+    }
+
+    let (access_token, refresh_token) = user.start_session(SessionOrigin::from(req))
+        .await?;
+
+    Ok(HttpResponse::Found()
+        .insert_header(("Location", app_url()))
+        .cookie(access_cookie(access_token))
+        .cookie(refresh_cookie(refresh_token))
+        .cookie(oauth_removal_cookie(OAUTH_STATE_COOKIE))
+        .finish())
+}
+
+#[get("/oauth/{provider}")]
+pub async fn oauth_authorize(name: Path<String>, params: Query<AuthorizeParams>) -> Result<HttpResponse, ApiError> {
+    let provider = provider(&name)
+        .ok_or(ApiError::not_found("The provider is not available."))?;
+
+    let state = generate_token();
+    let url = provider.authorize_url(&state)?;
+
+    Ok(HttpResponse::Found()
+        .insert_header(("Location", url.as_str()))
+        .cookie(oauth_state_cookie(match params.link {
+            true => format!("{state}:link"),
+            false => state
+        }))
+        .finish())
+}
+
+// logs in with a linked identity, links it to the logged in user or to the user
+// with the same verified email, otherwise the user is sent to choose a username.
+#[get("/oauth/{provider}/callback")]
+pub async fn oauth_callback(req: HttpRequest, name: Path<String>, params: Query<CallbackParams>, user: MaybeUser) -> Result<HttpResponse, ApiError> {
+    let provider = provider(&name)
+        .ok_or(ApiError::not_found("The provider is not available."))?;
+
+    callback(&req, provider, params.into_inner(), user)
+        .await
+}
+
+// the flow of the callback, apart from the configured providers so it can run against a mock one.
+async fn callback(req: &HttpRequest, provider: &dyn Provider, params: CallbackParams, user: MaybeUser) -> Result<HttpResponse, ApiError> {
+    let cookie = req.cookie(OAUTH_STATE_COOKIE);
+    let (state, link) = match cookie.as_ref().map(|cookie| cookie.value()) {
+        Some(value) => match value.strip_suffix(":link") {
+            Some(state) => (state, true),
+            None => (value, false)
+        },
+        None => ("", false)
+    };
+
+    if state.is_empty() || params.state.as_deref() != Some(state) {
+        return Err(ApiError::bad_request("The authorization state is not valid, try again."));
+    }
+
+    let code = params.code
+        .as_deref()
+        .ok_or(ApiError::bad_request("The authorization was denied."))?;
+
+    let ExternalIdentity { subject, username, email, email_verified } = provider.exchange(code)
+        .await?;
+
+    if link {
+        let MaybeUser::Authorized(user) = user
+        else {
+            return Err(ApiError::unauthorized("Log in to link an account."));
+        };
+
+        OAuthIdentity::link(user.id(), provider.name(), &subject)
+            .await?;
+
+        return Ok(HttpResponse::Found()
+            .insert_header(("Location", app_url()))
+            .cookie(oauth_removal_cookie(OAUTH_STATE_COOKIE))
+            .finish());
+    }
+
+    if let Some(identity) = OAuthIdentity::find(provider.name(), &subject).await? {
+        let user = User::find(identity.user_id())
+            .await?
+            .ok_or(ApiError::internal("The linked user doesn't exist."))?;
+
+        return log_in(req, &user).await;
+    }
+
+    // both sides confirmed they own the address, so it's the same person.
+    if let Some(email) = email.as_deref().filter(|_| email_verified) {
+        if let Some(user) = User::find_by_email(email).await?.filter(User::activated) {
+            OAuthIdentity::link(user.id(), provider.name(), &subject)
+                .await?;
+
+            return log_in(req, &user).await;
+        }
+    }
+
+    let signup = encode_jwt(&Claims::new(
+        OffsetDateTime::now_utc()
+            .add(SIGNUP_LIFETIME)
+            .unix_timestamp() as usize,
+        SignupClaims {
+            provider: provider.name().into(),
+            subject,
+            email: email.clone(),
+            email_verified
+        }
+    ))?;
+
+    // the front-end suggests the username of the provider and asks
+    // for an email when there's none.
+    let url = Url::parse_with_params(
+        &format!("{}/signup/oauth", app_url()),
+        [
+            ("username", username.as_str()),
+            ("email_required", if email.is_none() { "true" } else { "false" })
+        ]
+    )?;
+
+    Ok(HttpResponse::Found()
+        .insert_header(("Location", url.as_str()))
+        .cookie(oauth_removal_cookie(OAUTH_STATE_COOKIE))
+        .cookie(oauth_signup_cookie(signup))
+        .finish())
+}
+
+// creates the user for the identity in the signup cookie with the chosen username.
+#[post("/oauth/complete")]
+pub async fn oauth_complete(req: HttpRequest, params: FormOrJson<CompleteParams>) -> Result<HttpResponse, ApiError> {
+    let claims = req.cookie(OAUTH_SIGNUP_COOKIE)
+        .and_then(|cookie| decode_jwt::<Claims<SignupClaims>>(cookie.value()).ok())
+        .ok_or(ApiError::unauthorized("The signup expired, log in with the provider again."))?;
+
+    let SignupClaims { provider, subject, email, email_verified } = claims.into_inner();
+    let CompleteParams { username, email: chosen_email } = params.into_inner();
+    let username = normalize_username(&username);
+
+    // an email typed by the user still has to be confirmed.
+    let (email, activated) = match (email, chosen_email) {
+        (Some(email), _) => (email, email_verified),
+        (None, Some(email)) => (email, false),
+        (None, None) => {
+            return Err(ApiError::InvalidFields(
+                FieldErrors::from([("email", "An email is required.".into())])
+            ));
+        }
+    };
+
+    if OAuthIdentity::find(&provider, &subject).await?.is_some() {
+        return Err(ApiError::conflict("This account is already linked to another user."));
+    }
+
+    let fields = signup_field_errors(&username, &email)
+        .await?;
+
+    if !fields.is_empty() {
+        return Err(ApiError::InvalidFields(fields));
+    }
+
+    let user = User::insert_external(email, username, activated, &provider, &subject)
+        .await?;
+
+    if !activated {
+        user.send_activation()
+            .await?;
+    }
+
+    let (access_token, refresh_token) = user.start_session(SessionOrigin::from(&req))
+        .await?;
+
+    Ok(HttpResponse::Ok()
+        .cookie(access_cookie(access_token))
+        .cookie(refresh_cookie(refresh_token))
+        .cookie(oauth_removal_cookie(OAUTH_SIGNUP_COOKIE))
+        .json(Envelope::ok()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{future::Future, io::{BufRead, BufReader, Read, Write}, net::{TcpListener, TcpStream}, panic::{resume_unwind, AssertUnwindSafe}, thread};
+    use actix_web::{http::StatusCode, test::TestRequest, HttpResponse, ResponseError};
+    use futures_util::{future::BoxFuture, FutureExt};
+    use lazy_static::lazy_static;
+    use reqwest::Client;
+    use serde::Deserialize;
+    use serde_json::{json, Value};
+    use sqlx::query;
+    use tokio::runtime::{Builder, Runtime};
+    use uuid::Uuid;
+    use crate::{db, helpers::{http::{api_error::ApiError, cookies::{oauth_state_cookie, ACCESS_COOKIE, OAUTH_SIGNUP_COOKIE, TWO_FACTOR_COOKIE}, jwt::load_test_keyring}, mail::templates::app_url, oauth::provider::{ExternalIdentity, OAuthResult, Provider, ProviderConfig}}, models::{oauth_identity::OAuthIdentity, two_factor::TwoFactor, user::{MaybeUser, User}}};
+    use super::{callback, CallbackParams};
+
+    const STATE: &str = "expected-state";
+
+    lazy_static! {
+        // the database pool is shared by every test, so they all run on the same runtime.
+        static ref RUNTIME: Runtime = Builder::new_multi_thread()
+            .enable_all()
+            .build()
+            .expect("The test runtime can be built.");
+    }
+
+    // every test creates its users under an email domain of its own, they're
+    // deleted once it ends, even when it fails, so the database is left as it was.
+    fn run<F, T>(test: F)
+    where
+        F: FnOnce(String) -> T,
+        T: Future<Output = ()>
+    {
+        load_test_keyring();
+
+        let domain = format!("{}.invalid", unique_name());
+
+        let result = RUNTIME.block_on(AssertUnwindSafe(test(domain.clone())).catch_unwind());
+
+        RUNTIME.block_on(delete_users(&domain))
+            .expect("The users of the test can be deleted.");
+
+        if let Err(panic) = result {
+            resume_unwind(panic);
+        }
+    }
+
+    // the rest of their rows are deleted along with them.
+    async fn delete_users(domain: &str) -> Result<(), ApiError> {
+        query!(
+            r#"
+                DELETE FROM users
+                WHERE email LIKE '%@' || $1
+            "#,
+            domain
+        )
+            .execute(db!())
+            .await?;
+
+        Ok(())
+    }
+
+    // what the userinfo endpoint of the mock provider returns.
+    #[derive(Deserialize)]
+    struct MockUser {
+        sub: String,
+        username: String,
+        email: Option<String>,
+        email_verified: bool
+    }
+
+    struct MockProvider {
+        config: ProviderConfig
+    }
+
+    impl Provider for MockProvider {
+        fn name(&self) -> &'static str {
+            "mock"
+        }
+
+        fn config(&self) -> &ProviderConfig {
+            &self.config
+        }
+
+        fn identity<'p>(&'p self, client: &'p Client, access_token: &'p str) -> BoxFuture<'p, OAuthResult<ExternalIdentity>> {
+            Box::pin(async move {
+                let user = client
+                    .get(format!("{}/userinfo", self.config.api_url))
+                    .bearer_auth(access_token)
+                    .send()
+                    .await?
+                    .error_for_status()?
+                    .json::<MockUser>()
+                    .await?;
+
+                Ok(ExternalIdentity {
+                    subject: user.sub,
+                    username: user.username,
+                    email: user.email,
+                    email_verified: user.email_verified
+                })
+            })
+        }
+    }
+
+    // a provider whose token endpoint accepts any code and whose userinfo endpoint returns the user.
+    fn mock_provider(user: Value) -> MockProvider {
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .expect("The mock provider can listen.");
+
+        let url = format!("http://{}", listener.local_addr().expect("The mock provider has an address."));
+
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                respond(stream, &user);
+            }
+        });
+
+        MockProvider {
+            config: ProviderConfig {
+                client_id: "client".into(),
+                client_secret: "secret".into(),
+                authorize_url: format!("{url}/authorize"),
+                token_url: format!("{url}/token"),
+                api_url: url,
+                scopes: "identity"
+            }
+        }
+    }
+
+    fn respond(mut stream: TcpStream, user: &Value) {
+        let mut reader = BufReader::new(&stream);
+
+        let mut request_line = String::new();
+        let _ = reader.read_line(&mut request_line);
+
+        let mut content_length = 0;
+
+        loop {
+            let mut header = String::new();
+
+            if reader.read_line(&mut header).unwrap_or(0) == 0 || header == "\r\n" {
+                break;
+            }
+
+            if let Some((name, value)) = header.split_once(':') {
+                if name.eq_ignore_ascii_case("content-length") {
+                    content_length = value.trim().parse().unwrap_or(0);
+                }
+            }
+        }
+
+        let mut body = vec![0; content_length];
+        let _ = reader.read_exact(&mut body);
+
+        let (status, body) = match request_line.split(' ').nth(1) {
+            Some("/token") => ("200 OK", json!({ "access_token": "mock-access-token" })),
+            Some("/userinfo") => ("200 OK", user.clone()),
+            _ => ("404 Not Found", json!({}))
+        };
+
+        let body = body.to_string();
+
+        let _ = write!(
+            stream,
+            "HTTP/1.1 {status}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+            body.len()
+        );
+    }
+
+    fn params(state: &str) -> CallbackParams {
+        CallbackParams {
+            code: Some("mock-code".into()),
+            state: Some(state.into())
+        }
+    }
+
+    fn unique_name() -> String {
+        format!("oauth{}", &Uuid::new_v4().simple().to_string()[..12])
+    }
+
+    // an activated user whose email the provider may share.
+    async fn activated_user(domain: &str) -> User {
+        let username = unique_name();
+
+        User::insert_external(format!("{username}@{domain}"), username, true, "other", &Uuid::new_v4().to_string())
+            .await
+            .expect("The user can be created.")
+    }
+
+    fn location(res: &HttpResponse) -> &str {
+        res.headers()
+            .get("Location")
+            .and_then(|location| location.to_str().ok())
+            .unwrap_or_default()
+    }
+
+    fn has_cookie(res: &HttpResponse, name: &str) -> bool {
+        res.cookies()
+            .any(|cookie| cookie.name() == name && !cookie.value().is_empty())
+    }
+
+    #[test]
+    fn rejects_a_state_that_does_not_match_the_cookie() {
+        run(|_| async move {
+            let provider = mock_provider(json!({ "sub": "1", "username": "mock", "email": null, "email_verified": false }));
+
+            let req = TestRequest::default()
+                .cookie(oauth_state_cookie(STATE.into()))
+                .to_http_request();
+
+            let err = callback(&req, &provider, params("another-state"), MaybeUser::Unauthorized)
+                .await
+                .expect_err("A different state is rejected.");
+
+            assert_eq!(err.status_code(), StatusCode::BAD_REQUEST);
+
+            let err = callback(&TestRequest::default().to_http_request(), &provider, params(STATE), MaybeUser::Unauthorized)
+                .await
+                .expect_err("A callback without the state cookie is rejected.");
+
+            assert_eq!(err.status_code(), StatusCode::BAD_REQUEST);
+        });
+    }
+
+    #[test]
+    fn links_an_existing_account_only_when_the_email_is_verified() {
+        run(|domain| async move {
+            let user = activated_user(&domain).await;
+
+            let req = TestRequest::default()
+                .cookie(oauth_state_cookie(STATE.into()))
+                .to_http_request();
+
+            let unverified = Uuid::new_v4().to_string();
+            let provider = mock_provider(json!({ "sub": unverified, "username": "mock", "email": user.email(), "email_verified": false }));
+
+            let res = callback(&req, &provider, params(STATE), MaybeUser::Unauthorized)
+                .await
+                .expect("An unverified email goes to the signup.");
+
+            assert_eq!(res.status(), StatusCode::FOUND);
+            assert!(location(&res).starts_with(&format!("{}/signup/oauth", app_url())));
+            assert!(has_cookie(&res, OAUTH_SIGNUP_COOKIE));
+            assert!(!has_cookie(&res, ACCESS_COOKIE));
+            assert!(OAuthIdentity::find("mock", &unverified).await.expect("The identities can be read.").is_none());
+
+            let verified = Uuid::new_v4().to_string();
+            let provider = mock_provider(json!({ "sub": verified, "username": "mock", "email": user.email(), "email_verified": true }));
+
+            let res = callback(&req, &provider, params(STATE), MaybeUser::Unauthorized)
+                .await
+                .expect("A verified email logs in.");
+
+            assert_eq!(res.status(), StatusCode::FOUND);
+            assert_eq!(location(&res), app_url());
+            assert!(has_cookie(&res, ACCESS_COOKIE));
+
+            let identity = OAuthIdentity::find("mock", &verified)
+                .await
+                .expect("The identities can be read.")
+                .expect("The identity was linked.");
+
+            assert_eq!(identity.user_id(), user.id());
+        });
+    }
+
+    #[test]
+    fn sends_users_with_two_factor_to_enter_the_code() {
+        run(|domain| async move {
+            let subject = Uuid::new_v4().to_string();
+            let username = unique_name();
+
+            let user = User::insert_external(format!("{username}@{domain}"), username, true, "mock", &subject)
+                .await
+                .expect("The user can be created.");
+
+            TwoFactor::enroll(user.id(), user.email())
+                .await
+                .expect("The user can enroll.");
+
+            let enabled = async {
+                query!(
+                    r#"
+                        UPDATE two_factor
+                        SET enabled_at = NOW()
+                        WHERE user_id = $1
+                    "#,
+                    user.id()
+                )
+                    .execute(db!())
+                    .await?;
+
+                Ok::<_, ApiError>(())
+            };
+
+            enabled.await
+                .expect("Two-factor authentication can be enabled.");
+
+            let req = TestRequest::default()
+                .cookie(oauth_state_cookie(STATE.into()))
+                .to_http_request();
+
+            let provider = mock_provider(json!({ "sub": subject, "username": "mock", "email": null, "email_verified": false }));
+
+            let res = callback(&req, &provider, params(STATE), MaybeUser::Unauthorized)
+                .await
+                .expect("The login waits for the code.");
+
+            assert_eq!(res.status(), StatusCode::FOUND);
+            assert_eq!(location(&res), format!("{}/login/two-factor", app_url()));
+            assert!(has_cookie(&res, TWO_FACTOR_COOKIE));
+            assert!(!has_cookie(&res, ACCESS_COOKIE));
+        });
+    }
+}
@@ -0,0 +1,54 @@
+use std::time::Duration;
+use actix_web::{post, HttpResponse};
+use lazy_static::lazy_static;
+use serde::Deserialize;
+use crate::{helpers::{http::{api_error::ApiError, envelope::Envelope, form_or_json::FormOrJson, rate_limit::RateLimiter, socket_registry}, validation::validate_password}, models::user::User};
+
+lazy_static! {
+    static ref FORGOT_LIMITER: RateLimiter<String> = RateLimiter::new(3, Duration::from_secs(60 * 60));
+}
+
+#[derive(Deserialize)]
+struct ForgotParams {
+    email: String
+}
+
+#[derive(Deserialize)]
+struct ResetParams {
+    token: String,
+    password: String
+}
+
+// the response is the same whether the email exists or not,
+// so this can't be used to find out who has an account.
+#[post("/password/forgot")]
+pub async fn forgot_password(params: FormOrJson<ForgotParams>) -> Result<HttpResponse, ApiError> {
+    let ForgotParams { email } = params.into_inner();
+
+    if FORGOT_LIMITER.hit(email.to_lowercase()).is_ok() {
+        if let Some(user) = User::find_by_email(&email).await? {
+            user.send_password_reset()
+                .await?;
+        }
+    }
+
+    Ok(HttpResponse::Accepted()
+        .json(Envelope::ok()))
+}
+
+#[post("/password/reset")]
+pub async fn reset_password(params: FormOrJson<ResetParams>) -> Result<HttpResponse, ApiError> {
+    let ResetParams { token, password } = params.into_inner();
+
+    validate_password(&password)
+        .map_err(ApiError::bad_request)?;
+
+    let revoked = User::reset_password(&token, password)
+        .await?;
+
+    socket_registry::close_revoked(&revoked)
+        .await;
+
+    Ok(HttpResponse::Ok()
+        .json(Envelope::ok()))
+}
@@ -0,0 +1,60 @@
+use actix_web::{post, HttpRequest, HttpResponse};
+use serde::Serialize;
+use crate::{helpers::http::{api_error::ApiError, cookies::{access_cookie, refresh_cookie, removal_cookies, REFRESH_COOKIE}, envelope::Envelope, socket_registry}, models::{user::User, user_session::{SessionError, UserSession}}};
+
+// sockets can't be sent the http-only access cookie again,
+// browsers re-authenticate them with the ticket instead.
+#[derive(Serialize)]
+struct Refreshed {
+    socket_ticket: String
+}
+
+#[post("/refresh")]
+pub async fn refresh(req: HttpRequest) -> Result<HttpResponse, ApiError> {
+    let Some(cookie) = req.cookie(REFRESH_COOKIE)
+    else {
+        return Ok(unauthorized("Provide Refresh cookie for this endpoint.".into()));
+    };
+
+    let (session, refresh_token) = match UserSession::refresh(cookie.value()).await {
+        Ok(refreshed) => refreshed,
+
+        Err(err @ SessionError::RefreshTokenReused(session_id)) => {
+            socket_registry::close_revoked(&[session_id])
+                .await;
+
+            return Ok(unauthorized(format!("{err:#}")));
+        },
+
+        Err(err @ SessionError::InvalidRefreshToken) => {
+            return Ok(unauthorized(format!("{err:#}")));
+        },
+
+        Err(err) => {
+            return Err(err.into());
+        }
+    };
+
+    let Some(user) = User::find(session.user_id()).await?
+    else {
+        return Ok(unauthorized("The account does not exist.".into()));
+    };
+
+    Ok(HttpResponse::Ok()
+        .cookie(access_cookie(user.jwt(session.id()).await?))
+        .cookie(refresh_cookie(refresh_token))
+        .json(Envelope::data(Refreshed {
+            socket_ticket: user.socket_ticket(session.id())?
+        })))
+}
+
+// the cookies are removed too, so the client stops refreshing.
+fn unauthorized(message: String) -> HttpResponse {
+    let mut res = HttpResponse::Unauthorized();
+
+    for cookie in removal_cookies() {
+        res.cookie(cookie);
+    }
+
+    res.json(Envelope::error(message))
+}
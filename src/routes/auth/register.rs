@@ -1,8 +1,6 @@
-use actix_web::{cookie::{time::{Duration, OffsetDateTime}, Cookie}, post, web::Form, HttpResponse, Responder};
-use email_address::EmailAddress;
+use actix_web::{post, HttpRequest, HttpResponse};
 use serde::Deserialize;
-use crate::{grv, models::user::User};
-use std::ops::Add;
+use crate::{helpers::{http::{api_error::ApiError, cookies::{access_cookie, refresh_cookie}, envelope::{Envelope, FieldErrors}, form_or_json::FormOrJson}, validation::{normalize_username, validate_email, validate_password, validate_username}}, models::{user::{User, UserResult}, user_session::SessionOrigin, username_denylist::UsernameDenylist}};
 
 #[derive(Deserialize)]
 struct RegisterParams {
@@ -11,42 +9,57 @@ struct RegisterParams {
     password: String
 }
 
+// the username and email are only looked up once they are valid, the username
+// has to be normalized first. shared with the signups of external providers.
+pub async fn signup_field_errors(username: &String, email: &str) -> UserResult<FieldErrors> {
+    let mut fields = FieldErrors::new();
+
+    match validate_username(username) {
+        Ok(()) if UsernameDenylist::denies(username).await?
+            => fields.insert("username", "This username is not allowed.".into()),
+        Ok(()) if User::find_by_username(username).await?.is_some()
+            => fields.insert("username", "This username is already in use.".into()),
+        Ok(()) => None,
+        Err(err) => fields.insert("username", err.into())
+    };
+
+    match validate_email(email) {
+        Ok(()) if User::find_by_email(email).await?.is_some()
+            => fields.insert("email", "This email is already in use.".into()),
+        Ok(()) => None,
+        Err(err) => fields.insert("email", err.into())
+    };
+
+    Ok(fields)
+}
+
 #[post("/register")]
-pub async fn register(params: Form<RegisterParams>) -> impl Responder {
+pub async fn register(req: HttpRequest, params: FormOrJson<RegisterParams>) -> Result<HttpResponse, ApiError> {
     let RegisterParams { username, email, password } = params.into_inner();
+    let username = normalize_username(&username);
 
-    if username.len() > 25 || email.len() > 75 {
-        return HttpResponse::BadRequest()
-            .body("Max 25 characters per username and 75 characters per email allowed.");
-    }
-
-    if !EmailAddress::is_valid(&email) {
-        return HttpResponse::BadRequest()
-            .body("The email is not a valid email.");
-    }
+    let mut fields = signup_field_errors(&username, &email)
+        .await?;
 
-    if username.chars().any(|c| !c.is_alphanumeric() || c.is_whitespace()) {
-        return HttpResponse::BadRequest()
-            .body("Username must only contain readable characters");
+    if let Err(err) = validate_password(&password) {
+        fields.insert("password", err.into());
     }
 
-    if grv!(User::exists(&username, &email).await) {
-        return HttpResponse::BadRequest()
-            .body("This username or email already exists");
+    if !fields.is_empty() {
+        return Err(ApiError::InvalidFields(fields));
     }
 
-    let user = grv!(User::insert(email, username, password).await);
-    let mut cookie = Cookie::new("Session", grv!(user.jwt()));
-
-    cookie.set_expires(
-        OffsetDateTime::now_utc()
-            .add(Duration::hours(12))
-    );
+    let user = User::insert(email, username, password)
+        .await?;
 
-    cookie.set_path("/");
+    user.send_activation()
+        .await?;
 
-    HttpResponse::Ok()
-        .cookie(cookie)
-        .finish()
+    let (access_token, refresh_token) = user.start_session(SessionOrigin::from(&req))
+        .await?;
 
+    Ok(HttpResponse::Ok()
+        .cookie(access_cookie(access_token))
+        .cookie(refresh_cookie(refresh_token))
+        .json(Envelope::ok()))
 }
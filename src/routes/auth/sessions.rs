@@ -0,0 +1,50 @@
+use actix_web::{delete, get, web::Path, HttpResponse};
+use serde::Serialize;
+use uuid::Uuid;
+use crate::{helpers::http::{api_error::ApiError, cookies::removal_cookies, envelope::Envelope, socket_registry}, models::{user::SessionUser, user_session::{SessionDetails, UserSession}}};
+
+#[derive(Serialize)]
+struct ListedSession {
+    #[serde(flatten)]
+    details: SessionDetails,
+    // the session the list was requested from.
+    current: bool
+}
+
+#[get("/sessions")]
+pub async fn list_sessions(session: SessionUser) -> Result<HttpResponse, ApiError> {
+    let sessions = UserSession::list(session.user().id())
+        .await?
+        .into_iter()
+        .map(|details| ListedSession {
+            current: details.id() == session.session_id(),
+            details
+        })
+        .collect::<Vec<_>>();
+
+    Ok(HttpResponse::Ok()
+        .json(Envelope::data(sessions)))
+}
+
+// the sockets of the session are closed, revoking the current one works like a logout.
+#[delete("/sessions/{id}")]
+pub async fn revoke_session(session: SessionUser, id: Path<Uuid>) -> Result<HttpResponse, ApiError> {
+    let id = id.into_inner();
+
+    if !UserSession::revoke_owned(session.user().id(), id).await? {
+        return Err(ApiError::not_found("The session does not exist."));
+    }
+
+    socket_registry::close_revoked(&[id])
+        .await;
+
+    let mut res = HttpResponse::Ok();
+
+    if id == session.session_id() {
+        for cookie in removal_cookies() {
+            res.cookie(cookie);
+        }
+    }
+
+    Ok(res.json(Envelope::ok()))
+}
@@ -1,9 +1,12 @@
-use actix_web::{get, HttpResponse, Responder};
+use actix_web::{get, HttpResponse};
 
-use crate::models::user::User;
+use crate::{helpers::http::{api_error::ApiError, envelope::Envelope, require_scope::{ProfileRead, RequireScope}}, models::profile::PrivateProfile};
 
 #[get("/user")]
-pub async fn user(user: User) -> impl Responder {
-    HttpResponse::Ok()
-        .json(user)
+pub async fn user(session: RequireScope<ProfileRead>) -> Result<HttpResponse, ApiError> {
+    let profile = PrivateProfile::of(session.user())
+        .await?;
+
+    Ok(HttpResponse::Ok()
+        .json(Envelope::data(profile)))
 }
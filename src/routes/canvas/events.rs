@@ -0,0 +1,58 @@
+use std::time::Duration;
+use actix_web::{get, rt::time::sleep, web::Bytes, Error, HttpRequest, HttpResponse, Responder};
+use futures_util::{stream::{iter, unfold}, StreamExt};
+use tokio::{select, sync::broadcast::error::RecvError};
+use crate::helpers::cells::events::{subscribe, CellEvent};
+
+const KEEP_ALIVE: Duration = Duration::from_secs(15);
+
+fn cell_frame(event: &CellEvent) -> Result<Bytes, Error> {
+    Ok(Bytes::from(format!(
+        "id: {}\nevent: cell\ndata: {}\n\n",
+        event.seq(),
+        serde_json::to_string(event)?
+    )))
+}
+
+// tells the client it missed events that can't be resumed,
+// so it has to fetch the whole canvas again.
+fn reset_frame() -> Result<Bytes, Error> {
+    Ok(Bytes::from_static(b"event: reset\ndata: {}\n\n"))
+}
+
+#[get("/events")]
+pub async fn events(req: HttpRequest) -> impl Responder {
+    let last_event_id = req.headers()
+        .get("Last-Event-ID")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.trim().parse::<u64>().ok());
+
+    let (missed, receiver) = subscribe(last_event_id);
+
+    let missed = match missed {
+        Some(missed) => missed
+            .iter()
+            .map(|event| cell_frame(event))
+            .collect(),
+        None => vec![reset_frame()]
+    };
+
+    let live = unfold(receiver, |mut receiver| async move {
+        let frame = select! {
+            event = receiver.recv() => match event {
+                Ok(event) => cell_frame(&event),
+                Err(RecvError::Lagged(_)) => reset_frame(),
+                Err(RecvError::Closed) => return None
+            },
+
+            _ = sleep(KEEP_ALIVE) => Ok(Bytes::from_static(b": keep-alive\n\n"))
+        };
+
+        Some((frame, receiver))
+    });
+
+    HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .insert_header(("Cache-Control", "no-cache"))
+        .streaming(iter(missed).chain(live))
+}
@@ -1,3 +1,8 @@
 
 pub mod socket;
 pub mod auth;
+pub mod stats;
+pub mod canvas;
+pub mod users;
+pub mod account;
+pub mod admin;
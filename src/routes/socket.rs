@@ -1,29 +1,55 @@
-use actix_web::{get, rt::spawn, web::Payload, Error, HttpRequest, HttpResponse};
+use std::{collections::HashSet, time::Duration};
+use actix_web::{get, rt::{spawn, time::{sleep_until, Instant}}, web::Payload, HttpRequest, HttpResponse};
 use actix_ws::{handle, AggregatedMessage};
 use futures_util::StreamExt;
 use lazy_static::lazy_static;
-use tokio::sync::Mutex;
-use crate::{helpers::{cells::processes::{get_canvas_spec, process_written_cell}, http::{socket_messages::SocketMessage, socket_session::WsSession}}, models::user::MaybeUser};
-
+use log::error;
+use time::OffsetDateTime;
+use tokio::select;
+use crate::{helpers::{cells::{events, processes::{get_canvas_spec, process_written_cell}}, chat::filter::censor, http::{api_error::ApiError, cookies::ACCESS_COOKIE, origin::trusted_origin, cursor_throttle::CursorThrottle, rate_limit::RateLimiter, socket_messages::SocketMessage, socket_registry, socket_session::WsSession}}, models::{api_token::{ApiScope, ApiToken}, chat_message::ChatMessage, role::{Permission, Role}, two_factor::TwoFactor, user::{bearer_token, MaybeUser, SessionUser, User}, user_session::UserSession}};
 
 lazy_static! {
-    static ref SESSIONS: Mutex<Vec<WsSession>> = Mutex::new(Vec::new());
+    static ref CHAT_LIMITER: RateLimiter<i32> = RateLimiter::new(5, Duration::from_secs(10));
+    // keyed by user, so opening more tabs doesn't multiply the rate.
+    static ref CURSOR_THROTTLE: CursorThrottle<i32> = CursorThrottle::default();
 }
 
+
 macro_rules! send_text {
     ($session:expr, $value:expr) => {
         if !$session.text($value).await {
-            SESSIONS
-                .lock()
-                .await
-                .retain(|s| s != &$session);
+            socket_registry::unregister(&$session)
+                .await;
         }
     };
 }
 
 #[get("/session")]
-pub async fn session(req: HttpRequest, stream: Payload, user: MaybeUser) -> Result<HttpResponse, Error> {
-    let (res, session, stream) = handle(&req, stream)?;
+pub async fn session(req: HttpRequest, stream: Payload) -> Result<HttpResponse, ApiError> {
+    // a page of another site could open a socket with the cookies of the user.
+    if !trusted_origin(&req) {
+        return Err(ApiError::forbidden("The origin is not allowed to open a session."));
+    }
+
+    // bots send their api token as a header, browsers can only send the cookie.
+    let token = bearer_token(&req)
+        .or_else(|| req.cookie(ACCESS_COOKIE)
+            .map(|cookie| cookie.value().to_string())
+        );
+
+    let auth = match token {
+        Some(token) => SessionUser::from_token(token)
+            .await
+            .ok(),
+        None => None
+    };
+
+    if auth.as_ref().is_some_and(|auth| !auth.allows(ApiScope::CanvasRead)) {
+        return Err(ApiError::forbidden("The token needs the canvas:read scope to open a session."));
+    }
+
+    let (res, session, stream) = handle(&req, stream)
+        .map_err(ApiError::bad_request)?;
 
     let mut stream = stream
         .aggregate_continuations()
@@ -31,131 +57,345 @@ pub async fn session(req: HttpRequest, stream: Payload, user: MaybeUser) -> Resu
 
     let mut session = WsSession::new(
         session.clone(),
-        user
+        auth
     );
 
-    {
-        let mut sessions = SESSIONS
-            .lock()
-            .await;
+    let spec = match get_canvas_spec() {
+        Ok(spec) => spec,
+        Err(err) => {
+            session.close(Some(err)).await;
+            return Ok(res);
+        }
+    };
 
-        let mut session = session.clone();
+    if !session.text(SocketMessage::InitConnection(&session.user(), spec)).await {
+        return Ok(res);
+    }
 
-        let sent_init = session.text(
-            SocketMessage::InitConnection(
-                &session.user(),
-                match get_canvas_spec() {
-                    Ok(spec) => spec,
-                    Err(err) => {
-                        session.close(Some(err)).await;
-                        return Ok(res);
-                    }
-                }
-            )
-        )
-            .await;
+    if !session.text(SocketMessage::Roster(socket_registry::roster().await)).await {
+        return Ok(res);
+    }
+
+    for message in ChatMessage::history().await.unwrap_or_default() {
+        if !session.text(SocketMessage::SentChat(&message)).await {
+            return Ok(res);
+        }
+    }
 
-        if sent_init {
-            sessions.push(session);
+    if socket_registry::register(session.clone()).await {
+        if let MaybeUser::Authorized(user) = session.user() {
+            socket_registry::broadcast(SocketMessage::UserJoined(&user))
+                .await;
         }
     }
 
     spawn(async move {
-        while let Some(msg) = stream.next().await {
-            match msg {
-                Ok(AggregatedMessage::Text(text)) => {
-                    let MaybeUser::Authorized(mut user) = session.user()
+        loop {
+            let flush_at = match session.user() {
+                MaybeUser::Authorized(user) => CURSOR_THROTTLE.deadline(&user.id()),
+                MaybeUser::Unauthorized => None
+            };
+            let expires_at = session.expires_at();
+
+            select! {
+                msg = stream.next() => match msg {
+                    Some(Ok(AggregatedMessage::Text(text))) => {
+                        handle_text(&mut session, text.to_string())
+                            .await;
+                    },
+
+                    Some(Ok(AggregatedMessage::Ping(ping))) => {
+                        session.pong(&ping)
+                            .await;
+                    },
+
+                    None | Some(Err(_)) | Some(Ok(AggregatedMessage::Close(_))) => {
+                        break;
+                    },
+
+                    _ => {}
+                },
+
+                _ = sleep_until(flush_at.unwrap_or_else(Instant::now)), if flush_at.is_some() => {
+                    let MaybeUser::Authorized(user) = session.user()
                     else {
-                        send_text!(
-                            session,
-                            SocketMessage::SendError("Unauthorized.".into())
-                        );
+                        continue;
+                    };
 
+                    let Some(pos) = CURSOR_THROTTLE.flush(&user.id())
+                    else {
                         continue;
                     };
 
-                    let payload = SocketMessage::from(text.to_string());
+                    socket_registry::broadcast(SocketMessage::MovedCursor(&user, pos))
+                        .await;
+                },
 
-                    match payload {
-                        SocketMessage::WriteCell(pos, col) => {
+                _ = sleep_until(instant_at(expires_at)), if expires_at.is_some() => {
+                    // the browser keeps refreshing the session, revoked ones are closed by the registry.
+                    if let Some(refreshed) = refreshed_expiry(&session).await {
+                        session.extend(refreshed);
+                        continue;
+                    }
 
-                            if !user.can_consume_credit() {
-                                send_text!(
-                                    session,
-                                    SocketMessage::SendError(
-                                        "Cannot consume a token at this moment."
-                                            .into()
-                                    )
-                                );
+                    switch_user(&session, None)
+                        .await;
 
-                                continue;
-                            }
+                    send_text!(session, SocketMessage::SessionExpired);
+                }
+            }
+        }
 
-                            let consumption = user
-                                .consume_credit()
-                                .await;
+        if socket_registry::unregister(&session).await {
+            if let MaybeUser::Authorized(user) = session.user() {
+                socket_registry::broadcast(SocketMessage::UserLeft(&user))
+                    .await;
+            }
+        }
+    });
 
-                            if consumption.is_err() {
-                                send_text!(
-                                    session,
-                                    SocketMessage::SendError(
-                                        "Cannot consume a token at this moment.".into()
-                                    )
-                                );
+    Ok(res)
+}
 
-                                continue;
-                            }
+// converts an expiration date into the instant the runtime can wait for,
+// dates in the past are converted to the current instant.
+fn instant_at(at: Option<OffsetDateTime>) -> Instant {
+    Instant::now() + at
+        .and_then(|at| Duration::try_from(at - OffsetDateTime::now_utc()).ok())
+        .unwrap_or_default()
+}
 
-                            if let Err(err) = process_written_cell(&user, pos, col) {
-                                send_text!(session, SocketMessage::SendError(err));
+// when the access token of the session expires now, if it was refreshed since.
+async fn refreshed_expiry(ws_session: &WsSession) -> Option<OffsetDateTime> {
+    let session_id = ws_session.session_id()?;
 
-                                continue;
-                            }
-                        },
+    UserSession::access_expires_at(session_id)
+        .await
+        .ok()
+        .flatten()
+        .filter(|at| *at > OffsetDateTime::now_utc())
+}
 
-                        SocketMessage::SendError(_) => {
-                            send_text!(session, payload);
+// changes the user of the session announcing the presence changes it implies.
+async fn switch_user(ws_session: &WsSession, auth: Option<SessionUser>) {
+    let previous = ws_session.user();
 
-                            continue;
-                        },
+    let (left, joined) = socket_registry::change_user(ws_session, auth)
+        .await;
 
-                        _ => {}
-                    }
+    if let (true, MaybeUser::Authorized(previous)) = (left, previous) {
+        socket_registry::broadcast(SocketMessage::UserLeft(&previous))
+            .await;
+    }
 
-                    let sender = payload.to_sender(&user);
+    if let (true, MaybeUser::Authorized(current)) = (joined, ws_session.user()) {
+        socket_registry::broadcast(SocketMessage::UserJoined(&current))
+            .await;
+    }
+}
 
-                    let sender: String = match sender {
-                        SocketMessage::SendError(_) => {
-                            send_text!(session, sender);
+// the roles are read again instead of trusting the ones of the token, and like
+// RequireRole, the ones that need two-factor authentication only count with it enabled.
+async fn usable_permissions(user_id: i32) -> Option<HashSet<Permission>> {
+    let roles = Role::usable(
+        Role::of_user(user_id).await.ok()?,
+        TwoFactor::is_enabled(user_id).await.ok()?
+    )
+        .await
+        .ok()?;
+
+    Role::permissions(&roles)
+        .await
+        .ok()
+}
 
-                            continue;
-                        },
-                        _ => sender.into()
-                    };
+async fn handle_text(ws_session: &mut WsSession, text: String) {
+    let payload = SocketMessage::from(text);
 
-                    for session in SESSIONS.lock().await.iter_mut() {
-                        send_text!(*session, &sender);
-                    }
-                },
+    if let SocketMessage::Authenticate(token) = payload {
+        // browsers send the ticket they got when refreshing, bots their token.
+        let auth = match SessionUser::from_socket_ticket(&token).await {
+            Ok(auth) => Ok(auth),
+            Err(_) => SessionUser::from_token(token)
+                .await
+        };
+
+        match auth {
+            // the same scope the upgrade asks for, the session would keep receiving the canvas.
+            Ok(auth) if !auth.allows(ApiScope::CanvasRead) => {
+                send_text!(
+                    ws_session,
+                    SocketMessage::SendError("The token needs the canvas:read scope to open a session.".into())
+                );
+            },
+
+            Ok(auth) => {
+                switch_user(ws_session, Some(auth))
+                    .await;
+
+                send_text!(ws_session, SocketMessage::Authenticated(&ws_session.user()));
+            },
+
+            Err(_) => {
+                send_text!(
+                    ws_session,
+                    SocketMessage::SendError("The provided token is not valid.".into())
+                );
+            }
+        }
+
+        return;
+    }
+
+    let MaybeUser::Authorized(user) = ws_session.user()
+    else {
+        send_text!(
+            ws_session,
+            SocketMessage::SendError("Unauthorized.".into())
+        );
+
+        return;
+    };
+
+    // the session keeps the user it authenticated with,
+    // credits and activation are checked against the current one.
+    let mut user = match User::find(user.id()).await {
+        Ok(Some(user)) => user,
+        _ => {
+            send_text!(
+                ws_session,
+                SocketMessage::SendError("The session is not valid.".into())
+            );
+
+            return;
+        }
+    };
+
+    // unactivated accounts can watch and move around, but not change anything.
+    let mutating = matches!(payload, SocketMessage::WriteCell(..) | SocketMessage::SendChat(_));
+
+    if mutating && !user.activated() {
+        send_text!(ws_session, SocketMessage::ActivationRequired);
+
+        return;
+    }
+
+    let required = match payload {
+        SocketMessage::WriteCell(..) => Some((Permission::Paint, ApiScope::CanvasWrite)),
+        SocketMessage::SendChat(_) => Some((Permission::Chat, ApiScope::ChatWrite)),
+        _ => None
+    };
+
+    if let Some((permission, scope)) = required {
+        let allowed = ws_session.allows(scope) && usable_permissions(user.id())
+            .await
+            .is_some_and(|permissions| permissions.contains(&permission));
+
+        if !allowed {
+            send_text!(
+                ws_session,
+                SocketMessage::SendError("You don't have permission to do this.".into())
+            );
+
+            return;
+        }
+
+        if let Some(Err(retry)) = ws_session.api_token_id().map(ApiToken::hit_rate_limit) {
+            send_text!(
+                ws_session,
+                SocketMessage::SendError(format!(
+                    "Too many requests with this token, try again in {} seconds.",
+                    retry.as_secs() + 1
+                ))
+            );
+
+            return;
+        }
+    }
+
+    match payload {
+        SocketMessage::WriteCell(pos, col) => {
+
+            if !user.can_consume_credit() {
+                send_text!(
+                    ws_session,
+                    SocketMessage::SendError(
+                        "Cannot consume a token at this moment."
+                            .into()
+                    )
+                );
+
+                return;
+            }
+
+            let consumption = user
+                .consume_credit()
+                .await;
+
+            if consumption.is_err() {
+                send_text!(
+                    ws_session,
+                    SocketMessage::SendError(
+                        "Cannot consume a token at this moment.".into()
+                    )
+                );
+
+                return;
+            }
+
+            if let Err(err) = process_written_cell(&user, pos, col) {
+                send_text!(ws_session, SocketMessage::SendError(err));
+
+                return;
+            }
+
+            // the pixel is already written, failing here only skews the stats and the history.
+            let _ = user.record_pixel(pos, col).await;
+
+            events::publish(&user, pos, col);
+        },
+
+        SocketMessage::MoveCursor(pos) => {
+            if let Some(pos) = CURSOR_THROTTLE.push(user.id(), pos) {
+                socket_registry::broadcast(SocketMessage::MovedCursor(&user, pos))
+                    .await;
+            }
+        },
+
+        SocketMessage::SendChat(body) => {
+            if let Err(retry) = CHAT_LIMITER.hit(user.id()) {
+                send_text!(
+                    ws_session,
+                    SocketMessage::SendError(format!(
+                        "You are sending messages too fast, try again in {} seconds.",
+                        retry.as_secs() + 1
+                    ))
+                );
+
+                return;
+            }
 
-                Ok(AggregatedMessage::Ping(ping)) => {
-                    session.pong(&ping)
+            match ChatMessage::insert(&user, censor(&body)).await {
+                Ok(message) => {
+                    socket_registry::broadcast(SocketMessage::SentChat(&message))
                         .await;
                 },
 
-                Err(_) | Ok(AggregatedMessage::Close(_)) => {
-                    SESSIONS
-                        .lock()
-                        .await
-                        .retain(|s| s == &session);
+                Err(err) => {
+                    error!("{err:#}");
 
-                    break;
+                    send_text!(
+                        ws_session,
+                        SocketMessage::SendError("The message couldn't be sent, try again later.".into())
+                    );
                 }
-
-                _ => {}
             }
-        }
-    });
+        },
 
-    Ok(res)
+        SocketMessage::SendError(_) => {
+            send_text!(ws_session, payload);
+        },
+
+        _ => {}
+    }
 }
@@ -0,0 +1,8 @@
+use actix_web::{get, HttpResponse};
+use crate::{helpers::http::{api_error::ApiError, envelope::Envelope}, models::online::OnlineCount};
+
+#[get("/online")]
+pub async fn online() -> Result<HttpResponse, ApiError> {
+    Ok(HttpResponse::Ok()
+        .json(Envelope::data(OnlineCount::total().await?)))
+}
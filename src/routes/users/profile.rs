@@ -0,0 +1,12 @@
+use actix_web::{get, web::Path, HttpResponse};
+use crate::{helpers::http::{api_error::ApiError, envelope::Envelope}, models::{profile::PublicProfile, user::User}};
+
+#[get("/{username}")]
+pub async fn profile(username: Path<String>) -> Result<HttpResponse, ApiError> {
+    let user = User::find_by_username(&username)
+        .await?
+        .ok_or(ApiError::not_found("The user does not exist."))?;
+
+    Ok(HttpResponse::Ok()
+        .json(Envelope::data(PublicProfile::from(&user))))
+}